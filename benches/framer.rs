@@ -0,0 +1,52 @@
+//! Frames large replies like a full `get-configuration` or route table.
+//!
+//! Run with `cargo bench --bench framer`.
+
+use std::time::Instant;
+
+use rucli::netconf::framer::Framer;
+
+const CHUNK_SIZE: usize = 32 * 1024;
+
+fn reply(size: usize) -> Vec<u8> {
+    let line = b"<rt><rt-destination>10.0.0.0/24</rt-destination><rt-entry><protocol-name>BGP</protocol-name></rt-entry></rt>\n";
+    let mut reply = b"<rpc-reply><route-information>\n".to_vec();
+    while reply.len() < size {
+        reply.extend_from_slice(line);
+    }
+    reply.extend_from_slice(b"</route-information></rpc-reply>\n]]>]]>\n");
+    reply
+}
+
+fn bench(size: usize, messages: usize) {
+    let data = reply(size).repeat(messages);
+    let mut framer = Framer::new();
+
+    let start = Instant::now();
+    let mut framed = 0;
+    for chunk in data.chunks(CHUNK_SIZE) {
+        framer.push(chunk);
+        while let Some(message) = framer.next_message() {
+            framed += 1;
+            assert!(message.len() >= size);
+        }
+    }
+    let elapsed = start.elapsed();
+    assert_eq!(framed, messages);
+
+    let megabytes = data.len() as f64 / (1024.0 * 1024.0);
+    println!(
+        "{:>4} x {:>4} MB: {:>8.2?} ({:.0} MB/s)",
+        messages,
+        size / (1024 * 1024),
+        elapsed,
+        megabytes / elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+    bench(1024 * 1024, 50);
+    bench(50 * 1024 * 1024, 1);
+    bench(50 * 1024 * 1024, 3);
+    bench(200 * 1024 * 1024, 1);
+}
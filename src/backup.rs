@@ -0,0 +1,82 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// Removes volatile lines like the last commit timestamp and trailing whitespace,
+/// so an unchanged configuration results in an identical file.
+pub fn normalize(config: &str) -> String {
+    let mut normalized = String::new();
+    for line in config.lines() {
+        if line.starts_with("## Last commit:") || line.starts_with("## Last changed:") {
+            continue;
+        }
+        normalized.push_str(line.trim_end());
+        normalized.push('\n');
+    }
+    let trimmed = normalized.trim_matches('\n');
+    if trimmed.is_empty() {
+        return String::new();
+    }
+    format!("{}\n", trimmed)
+}
+
+/// Returns the routers listed in an inventory file, one per line, skipping blank
+/// lines and `#` comments.
+pub fn parse_inventory(inventory: &str) -> Vec<String> {
+    inventory
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Writes the text and set representation of a device configuration into `dest`.
+pub fn write(dest: &Path, hostname: &str, text: &str, set: &str) -> io::Result<()> {
+    fs::create_dir_all(dest)?;
+    fs::write(dest.join(format!("{}.conf", hostname)), normalize(text))?;
+    fs::write(dest.join(format!("{}.set", hostname)), normalize(set))?;
+    Ok(())
+}
+
+fn git(dest: &Path, args: &[&str]) -> io::Result<String> {
+    let output = Command::new("git").arg("-C").arg(dest).args(args).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Commits all backups in `dest` into a git repository, initializing it if needed.
+/// Returns the devices whose configuration changed, empty if nothing was committed.
+pub fn commit(dest: &Path) -> io::Result<Vec<String>> {
+    if !dest.join(".git").exists() {
+        git(dest, &["init", "--quiet"])?;
+    }
+    git(dest, &["add", "--all", "."])?;
+
+    let changed_files = git(dest, &["diff", "--cached", "--name-only"])?;
+    let devices: BTreeSet<String> = changed_files
+        .lines()
+        .filter_map(|file| Path::new(file).file_stem())
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .collect();
+    if devices.is_empty() {
+        return Ok(vec![]);
+    }
+    let devices: Vec<String> = devices.into_iter().collect();
+
+    let mut message = format!("Configuration backup: {}\n\nChanged devices:\n", devices.join(", "));
+    for device in &devices {
+        message.push_str(&format!("- {}\n", device));
+    }
+    git(dest, &["commit", "--quiet", "--message", &message])?;
+
+    Ok(devices)
+}
@@ -0,0 +1,308 @@
+use std::fmt::Write;
+
+use crate::netconf::xml::RPCError;
+use crate::statements::{self, ParseError, Statement};
+
+/// Splits an `error-path` like `[edit interfaces ge-0/0/0 unit 0]` into its words.
+fn error_path(error: &RPCError) -> Vec<&str> {
+    error
+        .error_path
+        .as_deref()
+        .map(|path| path.trim().trim_start_matches('[').trim_end_matches(']'))
+        .map(|path| path.split_whitespace().skip_while(|word| *word == "edit").collect())
+        .unwrap_or_default()
+}
+
+fn bad_element(error: &RPCError) -> Option<&str> {
+    error
+        .error_info
+        .as_ref()
+        .map(|info| info.bad_element.trim())
+        .filter(|element| !element.is_empty())
+}
+
+/// Removes quoted strings and comments, so braces within them are not counted.
+fn strip_line(line: &str) -> String {
+    let mut stripped = String::new();
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for c in line.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            '#' if !in_quotes => break,
+            _ if in_quotes => {}
+            c => stripped.push(c),
+        }
+    }
+    stripped
+}
+
+fn is_separator(c: char) -> bool {
+    c.is_whitespace() || matches!(c, ';' | '{' | '}' | '"')
+}
+
+fn contains_word(line: &str, word: &str) -> bool {
+    find_word(line, word).is_some()
+}
+
+/// Returns the byte offset of `word` in `line`, where it is not part of a longer word.
+fn find_word(line: &str, word: &str) -> Option<usize> {
+    line.match_indices(word).map(|(i, _)| i).find(|&i| {
+        let before = line[..i].chars().next_back();
+        let after = line[i + word.len()..].chars().next();
+        before.is_none_or(is_separator) && after.is_none_or(is_separator)
+    })
+}
+
+/// Finds the line of a text configuration an error refers to, 0-based.
+///
+/// The innermost block matching the `error-path` is searched for the bad element,
+/// falling back to the block itself.
+fn locate_text(source: &str, path: &[&str], bad_element: Option<&str>) -> Option<usize> {
+    let mut stack: Vec<Vec<String>> = vec![];
+    // (line, depth) of the deepest block whose path equals the error path
+    let mut block: Option<(usize, usize)> = None;
+
+    for (number, line) in source.lines().enumerate() {
+        let stripped = strip_line(line);
+        let trimmed = stripped.trim();
+
+        if let Some((start, depth)) = block {
+            if stack.len() == depth {
+                if let Some(element) = bad_element {
+                    if contains_word(line, element) && number != start {
+                        return Some(number);
+                    }
+                }
+            }
+        }
+
+        if let Some(header) = trimmed.strip_suffix('{') {
+            stack.push(header.split_whitespace().map(String::from).collect());
+            let words: Vec<&str> = stack.iter().flatten().map(String::as_str).collect();
+            if !path.is_empty() && words == path {
+                block = Some((number, stack.len()));
+            }
+        } else if trimmed.starts_with('}') {
+            if let Some((start, depth)) = block {
+                if stack.len() == depth {
+                    return Some(start);
+                }
+            }
+            stack.pop();
+        }
+    }
+
+    if let Some((start, _)) = block {
+        return Some(start);
+    }
+    bad_element.and_then(|element| source.lines().position(|line| contains_word(line, element)))
+}
+
+/// Finds the set statement an error refers to, 0-based.
+fn locate_set(source: &str, path: &[&str], bad_element: Option<&str>) -> Option<usize> {
+    let matches_path = |line: &str| {
+        let words: Vec<&str> = line.split_whitespace().skip(1).collect();
+        !path.is_empty() && words.starts_with(path)
+    };
+    let lines: Vec<&str> = source.lines().collect();
+
+    if let Some(element) = bad_element {
+        if let Some(number) = lines
+            .iter()
+            .position(|line| matches_path(line) && contains_word(line, element))
+        {
+            return Some(number);
+        }
+        if let Some(number) = lines.iter().position(|line| contains_word(line, element)) {
+            return Some(number);
+        }
+    }
+    lines.iter().position(|line| matches_path(line))
+}
+
+/// Finds the line in `source` that caused `error`, 0-based.
+///
+/// `format` is the format the configuration was loaded in, `text` or `set`.
+pub fn locate(source: &str, format: &str, error: &RPCError) -> Option<usize> {
+    let path = error_path(error);
+    let bad_element = bad_element(error);
+    match format {
+        "set" => locate_set(source, &path, bad_element),
+        _ => locate_text(source, &path, bad_element),
+    }
+}
+
+/// Renders `message` in the style of a compiler diagnostic, showing `context` lines
+/// around line `number` of `source`, 0-based, and marking `marker` if it is on it.
+fn snippet(
+    source: &str,
+    file_name: &str,
+    severity: &str,
+    message: &str,
+    number: usize,
+    marker: Option<&str>,
+    context: usize,
+) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let first = number.saturating_sub(context);
+    let last = (number + context).min(lines.len().saturating_sub(1));
+    let width = (last + 1).to_string().len();
+
+    let mut out = String::new();
+    let _ = writeln!(out, "{}: {}", severity, message);
+    let _ = writeln!(out, "{:width$}--> {}:{}", "", file_name, number + 1);
+    let _ = writeln!(out, "{:width$} |", "");
+    for (i, line) in lines.iter().enumerate().take(last + 1).skip(first) {
+        let _ = writeln!(out, "{:>width$} | {}", i + 1, line);
+        if i != number {
+            continue;
+        }
+        if let Some((column, marker)) = marker.and_then(|marker| Some((find_word(line, marker)?, marker))) {
+            let _ = writeln!(out, "{:width$} | {}{}", "", " ".repeat(column), "^".repeat(marker.len()));
+        }
+    }
+    let _ = write!(out, "{:width$} |", "");
+    out
+}
+
+/// Renders `error` in the style of a compiler diagnostic, showing `context`
+/// lines around the offending line of `source`.
+pub fn render(source: &str, format: &str, file_name: &str, error: &RPCError, context: usize) -> Option<String> {
+    let number = locate(source, format, error)?;
+    Some(snippet(
+        source,
+        file_name,
+        &error.error_severity,
+        error.error_message.trim(),
+        number,
+        bad_element(error),
+        context,
+    ))
+}
+
+/// Renders `error` of loading `statements` as a `configuration-set`, like [`render`],
+/// but against the `source` they were parsed from.
+pub fn render_statements(
+    source: &str,
+    statements: &[Statement],
+    file_name: &str,
+    error: &RPCError,
+    context: usize,
+) -> Option<String> {
+    let config = statements::to_configuration_set(statements);
+    let number = locate(&config, "set", error)?;
+    // A statement takes several lines of `config` if a quoted word spans lines
+    let mut end = 0;
+    let statement = statements.iter().find(|statement| {
+        end += statement.to_string().matches('\n').count() + 1;
+        number < end
+    })?;
+    Some(snippet(
+        source,
+        file_name,
+        &error.error_severity,
+        error.error_message.trim(),
+        statement.line().saturating_sub(1),
+        bad_element(error),
+        context,
+    ))
+}
+
+/// Renders an error found in `source` without the router, like [`render`].
+pub fn render_parse_error(source: &str, file_name: &str, error: &ParseError, context: usize) -> String {
+    snippet(source, file_name, "error", &error.message, error.line.saturating_sub(1), None, context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::netconf::xml::RPCErrorInfo;
+
+    const TEXT: &str = "\
+interfaces {
+    ge-0/0/0 {
+        unit 0 {
+            family inet {
+                address 192.0.2.1/33;
+            }
+        }
+    }
+    ge-0/0/1 {
+        description \"uplink; {primary}\";
+        unit 0 {
+            family inet {
+                address 192.0.2.1/33;
+            }
+        }
+    }
+}";
+
+    const SET: &str = "\
+set interfaces ge-0/0/0 unit 0 family inet address 192.0.2.1/33
+set interfaces ge-0/0/1 description uplink
+set interfaces ge-0/0/1 unit 0 family inet address 192.0.2.1/33";
+
+    fn rpc_error(path: &str, bad_element: Option<&str>) -> RPCError {
+        RPCError {
+            error_severity: "error".to_string(),
+            error_message: "invalid prefix length".to_string(),
+            error_path: Some(path.to_string()),
+            error_type: None,
+            error_tag: None,
+            error_info: bad_element.map(|bad_element| RPCErrorInfo {
+                bad_element: bad_element.to_string(),
+            }),
+            source_daemon: None,
+        }
+    }
+
+    #[test]
+    fn text_error_path_and_bad_element() {
+        let error = rpc_error("[edit interfaces ge-0/0/1 unit 0 family inet]", Some("192.0.2.1/33"));
+        assert_eq!(locate(TEXT, "text", &error), Some(12));
+        // Without the bad element within the block, the block itself is shown
+        let error = rpc_error("[edit interfaces ge-0/0/1 unit 0 family inet]", Some("mtu"));
+        assert_eq!(locate(TEXT, "text", &error), Some(11));
+        let error = rpc_error("[edit interfaces ge-0/0/1]", None);
+        assert_eq!(locate(TEXT, "text", &error), Some(8));
+    }
+
+    #[test]
+    fn set_error_path_and_bad_element() {
+        let error = rpc_error("[edit interfaces ge-0/0/1 unit 0 family inet]", Some("192.0.2.1/33"));
+        assert_eq!(locate(SET, "set", &error), Some(2));
+        let error = rpc_error("[edit interfaces ge-0/0/1]", None);
+        assert_eq!(locate(SET, "set", &error), Some(1));
+        let error = rpc_error("[edit protocols]", Some("192.0.2.1/33"));
+        assert_eq!(locate(SET, "set", &error), Some(0));
+    }
+
+    #[test]
+    fn statements_after_multi_line_string() {
+        let source = "\
+set system login message \"line one
+line two\"
+set system host-name r1; set interfaces ge-0/0/1 unit 0 family inet address 192.0.2.1/33";
+        let statements = statements::parse(source).unwrap();
+        let error = rpc_error("[edit interfaces ge-0/0/1 unit 0 family inet]", Some("192.0.2.1/33"));
+        let rendered = render_statements(source, &statements, "change.set", &error, 1).unwrap();
+        assert_eq!(
+            rendered,
+            "\
+error: invalid prefix length
+ --> change.set:3
+  |
+2 | line two\"
+3 | set system host-name r1; set interfaces ge-0/0/1 unit 0 family inet address 192.0.2.1/33
+  |                                                                             ^^^^^^^^^^^^
+  |"
+        );
+
+        let error = rpc_error("[edit system login]", Some("message"));
+        let rendered = render_statements(source, &statements, "change.set", &error, 0).unwrap();
+        assert!(rendered.contains("--> change.set:1"));
+    }
+}
@@ -0,0 +1,88 @@
+use std::io;
+use std::path::PathBuf;
+
+use crate::netconf::error::NETCONFError;
+use crate::ssh::error::SSHError;
+
+/// Any failure of a rucli invocation.
+///
+/// Each variant maps onto a process exit code, see [`Error::exit_code`].
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Connection failed: {0}")]
+    Ssh(SSHError),
+    #[error("{0}")]
+    Auth(SSHError),
+    #[error("{0}")]
+    Netconf(#[from] NETCONFError),
+    #[error("Config load failed: {0}")]
+    Load(NETCONFError),
+    #[error("Cannot read {}: {source}", path.display())]
+    ConfigFile { path: PathBuf, source: io::Error },
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("Invalid JSON output: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Usage(String),
+    #[error("{0} health check(s) failed, not confirming the commit so the router rolls it back")]
+    HealthCheck(usize),
+    #[error("Router unreachable after the commit ({0}), it rolls back once the confirm timeout expires")]
+    Unreachable(Box<Error>),
+    #[error("{0} syntax error(s) in the configuration")]
+    Lint(usize),
+    #[error("{0} change(s) to protected configuration, not committing")]
+    Protected(usize),
+    #[error("Sending `{0}` directly is refused with guardrails, commit with apply, edit-config or the shell")]
+    GuardedRpc(String),
+    #[error("Reading input in the shell failed: {0}")]
+    Shell(io::Error),
+    #[error("The confirm timeout expired before the commit could be confirmed, the router rolls it back")]
+    ConfirmExpired,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl From<SSHError> for Error {
+    fn from(err: SSHError) -> Self {
+        match err {
+            SSHError::AuthenticationFailed { .. } => Error::Auth(err),
+            _ => Error::Ssh(err),
+        }
+    }
+}
+
+impl Error {
+    /// The exit code of the CLI for this error.
+    ///
+    /// * `1`: the device rejected an RPC or replied unexpectedly
+    /// * `2`: invalid usage
+    /// * `3`: the SSH connection failed
+    /// * `4`: authentication failed
+    /// * `5`: a local file could not be read or written
+    /// * `6`: connecting or waiting for a reply timed out
+    /// * `7`: health checks failed after a confirmed commit
+    /// * `8`: the router could not be reached again after a confirmed commit
+    /// * `9`: the configuration has syntax errors
+    /// * `10`: the change touches hierarchies protected by the guardrails
+    /// * `11`: the confirm timeout expired before the commit could be confirmed
+    /// * `12`: the interactive shell could not read from the terminal
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Ssh(SSHError::Timeout(_))
+            | Error::Netconf(NETCONFError::Timeout(_))
+            | Error::Load(NETCONFError::Timeout(_)) => 6,
+            Error::Netconf(_) | Error::Load(_) | Error::Json(_) => 1,
+            Error::Usage(_) => 2,
+            Error::Ssh(_) => 3,
+            Error::Auth(_) => 4,
+            Error::ConfigFile { .. } | Error::Io(_) => 5,
+            Error::HealthCheck(_) => 7,
+            Error::Unreachable(_) => 8,
+            Error::Lint(_) => 9,
+            Error::Protected(_) | Error::GuardedRpc(_) => 10,
+            Error::ConfirmExpired => 11,
+            Error::Shell(_) => 12,
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::Deserialize;
+
+use rucli::Error;
+
+/// The guardrails of the system, which apply in addition to the `--guardrails` file.
+pub const SYSTEM_FILE: &str = "/etc/rucli/guardrails.yaml";
+
+/// The RPCs committing the candidate, which would bypass the diff check if sent raw.
+const COMMIT_RPCS: &[&str] = &["commit", "commit-configuration"];
+
+/// Configuration hierarchies commits must not change, read from a YAML file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Guardrails {
+    /// Hierarchies that are never changed, like `system login`
+    #[serde(default)]
+    pub forbidden: Vec<String>,
+    /// Hierarchies only changed with `--allow-protected`
+    #[serde(default)]
+    pub protected: Vec<String>,
+}
+
+/// A change of the candidate within a hierarchy of the guardrails.
+#[derive(Debug)]
+pub struct Violation {
+    /// The changed statement
+    pub path: String,
+    /// The hierarchy it is in
+    pub hierarchy: String,
+    pub forbidden: bool,
+}
+
+/// Splits a statement or hierarchy into its words, without quotes and without the
+/// `inactive:` and `protect:` tags.
+fn words(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .take_while(|word| !word.starts_with('#'))
+        .filter(|word| !matches!(*word, "inactive:" | "protect:" | "replace:"))
+        .map(|word| word.trim_matches('"').to_string())
+        .collect()
+}
+
+/// Returns the paths of all statements a `show | compare` diff adds, removes or
+/// (de)activates.
+///
+/// The path is the `[edit ..]` header of the hunk followed by the enclosing blocks
+/// and the statement itself.
+fn changed_paths(diff: &str) -> Vec<Vec<String>> {
+    let mut paths = vec![];
+    let mut header: Vec<String> = vec![];
+    let mut blocks: Vec<Vec<String>> = vec![];
+    for line in diff.lines() {
+        let trimmed = line.trim();
+        if let Some(edit) = trimmed.strip_prefix("[edit").and_then(|rest| rest.strip_suffix(']')) {
+            header = words(edit);
+            blocks.clear();
+            continue;
+        }
+        let changed = line.starts_with(['+', '-', '!']);
+        let content = if changed { line[1..].trim() } else { trimmed };
+        if content.starts_with('}') {
+            blocks.pop();
+            continue;
+        }
+        // A deactivated block is shown folded as `inactive: name { ... }`.
+        let (statement, opens) = match content.strip_suffix('{') {
+            Some(statement) => (statement, true),
+            None => (content.trim_end_matches(';').trim_end_matches("{ ... }"), false),
+        };
+        let statement = words(statement);
+        if statement.is_empty() {
+            continue;
+        }
+        if changed {
+            let mut path = header.clone();
+            path.extend(blocks.iter().flatten().cloned());
+            path.extend(statement.iter().cloned());
+            paths.push(path);
+        }
+        if opens {
+            blocks.push(statement);
+        }
+    }
+    paths
+}
+
+impl Guardrails {
+    /// Returns the changes of `diff` within the forbidden or protected hierarchies.
+    pub fn violations(&self, diff: &str) -> Vec<Violation> {
+        let rules = self
+            .forbidden
+            .iter()
+            .map(|hierarchy| (hierarchy, true))
+            .chain(self.protected.iter().map(|hierarchy| (hierarchy, false)));
+        let rules: Vec<(Vec<String>, &String, bool)> = rules
+            .map(|(hierarchy, forbidden)| (words(hierarchy.trim_start_matches("[edit").trim_end_matches(']')), hierarchy, forbidden))
+            .filter(|(words, _, _)| !words.is_empty())
+            .collect();
+
+        let mut violations: Vec<Violation> = vec![];
+        for path in changed_paths(diff) {
+            // Forbidden hierarchies come first, so they win over protected ones.
+            let Some((_, hierarchy, forbidden)) = rules.iter().find(|(words, _, _)| path.starts_with(words)) else {
+                continue;
+            };
+            let path = path.join(" ");
+            if violations.iter().any(|violation| violation.path == path) {
+                continue;
+            }
+            violations.push(Violation {
+                path,
+                hierarchy: hierarchy.to_string(),
+                forbidden: *forbidden,
+            });
+        }
+        violations
+    }
+
+    /// Prints the changes to guarded hierarchies in `diff` and fails unless all of them
+    /// are protected ones and `allow_protected` is set.
+    pub fn check(&self, diff: &str, allow_protected: bool) -> Result<(), Error> {
+        let mut refused = 0;
+        for violation in self.violations(diff) {
+            let kind = match (violation.forbidden, allow_protected) {
+                (true, _) => "forbidden",
+                (false, false) => "protected",
+                (false, true) => "allowed",
+            };
+            eprintln!("{} change of {}: {}", kind, violation.hierarchy, violation.path);
+            if kind != "allowed" {
+                refused += 1;
+            }
+        }
+        if refused > 0 {
+            return Err(Error::Protected(refused));
+        }
+        Ok(())
+    }
+
+    /// Refuses a raw RPC committing the candidate, as its changes are not checked.
+    pub fn check_rpc(&self, body: &str) -> Result<(), Error> {
+        match rpc_name(body) {
+            Some(name) if COMMIT_RPCS.contains(&name.as_str()) => Err(Error::GuardedRpc(name)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Returns the name of the RPC in `body`, the root element or the one within `<rpc>`.
+fn rpc_name(body: &str) -> Option<String> {
+    let mut reader = Reader::from_str(body);
+    loop {
+        let name = match reader.read_event() {
+            Ok(Event::Start(element)) | Ok(Event::Empty(element)) => {
+                String::from_utf8_lossy(element.local_name().as_ref()).into_owned()
+            }
+            Ok(Event::Eof) | Err(_) => return None,
+            Ok(_) => continue,
+        };
+        if name != "rpc" {
+            return Some(name);
+        }
+    }
+}
+
+/// Reads the guardrails of the system, if any, and those of `path`.
+pub fn load_all(path: Option<&Path>) -> Result<Option<Guardrails>, Error> {
+    let system = Path::new(SYSTEM_FILE);
+    let mut guardrails = system.exists().then(|| load(system)).transpose()?;
+    if let Some(path) = path {
+        let user = load(path)?;
+        let merged = guardrails.get_or_insert_with(Guardrails::default);
+        merged.forbidden.extend(user.forbidden);
+        merged.protected.extend(user.protected);
+    }
+    Ok(guardrails)
+}
+
+/// Reads a guardrails file.
+pub fn load(path: &Path) -> Result<Guardrails, Error> {
+    let config_file_error = |source| Error::ConfigFile {
+        path: path.to_path_buf(),
+        source,
+    };
+    let data = fs::read_to_string(path).map_err(config_file_error)?;
+    serde_yaml::from_str(&data).map_err(|e| config_file_error(io::Error::new(io::ErrorKind::InvalidData, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(diff: &str) -> Vec<String> {
+        changed_paths(diff).iter().map(|path| path.join(" ")).collect()
+    }
+
+    #[test]
+    fn added_statement() {
+        assert_eq!(paths("[edit system]\n-  host-name r2;\n+  host-name r1;\n"), ["system host-name r2", "system host-name r1"]);
+    }
+
+    #[test]
+    fn deleted_block() {
+        let diff = "[edit system login]\n-    user bob {\n-        uid 2001;\n-        class super-user;\n-    }\n";
+        assert_eq!(
+            paths(diff),
+            ["system login user bob", "system login user bob uid 2001", "system login user bob class super-user"]
+        );
+    }
+
+    #[test]
+    fn deactivated_statements() {
+        let diff = "[edit interfaces ge-0/0/0 unit 0 family inet]\n\
+                    !      inactive: address 192.0.2.1/24;\n\
+                    [edit protocols]\n\
+                    !      inactive: bgp { ... }\n";
+        assert_eq!(paths(diff), ["interfaces ge-0/0/0 unit 0 family inet address 192.0.2.1/24", "protocols bgp"]);
+    }
+
+    #[test]
+    fn nested_hunk() {
+        let diff = "[edit protocols bgp]\n\
+                    \x20    group ext {\n\
+                    +        neighbor 192.0.2.2 {\n\
+                    +            peer-as 65001;\n\
+                    +        }\n\
+                    \x20    }\n\
+                    +    group int {\n\
+                    +        type internal;\n\
+                    +    }\n\
+                    [edit]\n\
+                    +  snmp {\n\
+                    +      community \"public\";\n\
+                    +  }\n";
+        assert_eq!(
+            paths(diff),
+            [
+                "protocols bgp group ext neighbor 192.0.2.2",
+                "protocols bgp group ext neighbor 192.0.2.2 peer-as 65001",
+                "protocols bgp group int",
+                "protocols bgp group int type internal",
+                "snmp",
+                "snmp community public",
+            ]
+        );
+    }
+
+    #[test]
+    fn violations() {
+        let guardrails = Guardrails {
+            forbidden: vec!["[edit system login]".to_string()],
+            protected: vec!["system".to_string(), "protocols bgp".to_string()],
+        };
+        let diff = "[edit system]\n\
+                    +  login-banner \"hi\";\n\
+                    [edit system login]\n\
+                    -    user bob {\n\
+                    -        class super-user;\n\
+                    -    }\n\
+                    [edit protocols]\n\
+                    !      inactive: bgp { ... }\n\
+                    [edit interfaces]\n\
+                    +   lo0 {\n\
+                    +       description test;\n\
+                    +   }\n";
+        let violations: Vec<(String, String, bool)> = guardrails
+            .violations(diff)
+            .into_iter()
+            .map(|violation| (violation.path, violation.hierarchy, violation.forbidden))
+            .collect();
+        let expected = [
+            ("system login-banner hi", "system", false),
+            ("system login user bob", "[edit system login]", true),
+            ("system login user bob class super-user", "[edit system login]", true),
+            ("protocols bgp", "protocols bgp", false),
+        ];
+        let expected: Vec<(String, String, bool)> = expected
+            .iter()
+            .map(|(path, hierarchy, forbidden)| (path.to_string(), hierarchy.to_string(), *forbidden))
+            .collect();
+        assert_eq!(violations, expected);
+
+        assert!(guardrails.check(diff, true).is_err());
+        let protected_only = "[edit protocols]\n!      inactive: bgp { ... }\n";
+        assert!(guardrails.check(protected_only, true).is_ok());
+        assert!(matches!(guardrails.check(protected_only, false), Err(Error::Protected(1))));
+    }
+
+    #[test]
+    fn commit_rpcs() {
+        let guardrails = Guardrails::default();
+        assert!(guardrails.check_rpc("<commit-configuration><check/></commit-configuration>").is_err());
+        assert!(guardrails.check_rpc(r#"<rpc message-id="1"><commit/></rpc>"#).is_err());
+        assert!(guardrails.check_rpc("<get-configuration/>").is_ok());
+    }
+}
@@ -0,0 +1,171 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use rucli::netconf::{json, NETCONFClient};
+use rucli::Error;
+
+/// Operational checks run after a confirmed commit, read from a YAML file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HealthChecks {
+    /// Seconds to wait after the commit before running the first check
+    #[serde(default)]
+    pub settle: u64,
+    pub checks: Vec<Check>,
+}
+
+/// An operational command and what its output is expected to look like.
+///
+/// With a `query` the command is run with JSON output, which is flattened and
+/// queried with JMESPath before comparing it to `equals`. Otherwise the text output
+/// is matched against `contains` and `not_contains`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Check {
+    pub name: Option<String>,
+    pub command: String,
+    pub contains: Option<String>,
+    pub not_contains: Option<String>,
+    pub query: Option<String>,
+    pub equals: Option<serde_json::Value>,
+    /// How often a failing check is repeated, e.g. while BGP sessions come up
+    #[serde(default)]
+    pub retries: u32,
+    /// Seconds between retries
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+fn default_interval() -> u64 {
+    10
+}
+
+impl Check {
+    fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.command)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.equals.is_some() && self.query.is_none() {
+            return Err(format!("{}: equals requires a query", self.name()));
+        }
+        if self.contains.is_none() && self.not_contains.is_none() && self.equals.is_none() {
+            return Err(format!("{}: no expectation given", self.name()));
+        }
+        if let Some(query) = &self.query {
+            jmespath::compile(query).map_err(|e| format!("{}: invalid query: {}", self.name(), e))?;
+        }
+        Ok(())
+    }
+
+    /// Runs the command once, returning why the check failed.
+    fn run_once(&self, netconf_session: &mut NETCONFClient) -> Result<(), String> {
+        let format = if self.query.is_some() { "json" } else { "text" };
+        let output = netconf_session
+            .send_command(self.command.clone(), format.to_string())
+            .map_err(|e| e.to_string())?
+            .value;
+
+        if let Some(text) = &self.contains {
+            if !output.contains(text.as_str()) {
+                return Err(format!("output does not contain {:?}", text));
+            }
+        }
+        if let Some(text) = &self.not_contains {
+            if output.contains(text.as_str()) {
+                return Err(format!("output contains {:?}", text));
+            }
+        }
+        if let Some(query) = &self.query {
+            let value: serde_json::Value = serde_json::from_str(&output).map_err(|e| e.to_string())?;
+            let expression = jmespath::compile(query).map_err(|e| e.to_string())?;
+            let result = expression.search(json::flatten(value)).map_err(|e| e.to_string())?;
+            let result = serde_json::to_value(result).map_err(|e| e.to_string())?;
+            if let Some(expected) = &self.equals {
+                if &result != expected {
+                    return Err(format!("{} is {}, expected {}", query, result, expected));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads and validates a health check file.
+pub fn load(path: &Path) -> Result<HealthChecks, Error> {
+    let config_file_error = |source| Error::ConfigFile {
+        path: path.to_path_buf(),
+        source,
+    };
+    let data = fs::read_to_string(path).map_err(config_file_error)?;
+    let checks: HealthChecks = serde_yaml::from_str(&data)
+        .map_err(|e| config_file_error(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+    for check in &checks.checks {
+        check
+            .validate()
+            .map_err(|e| config_file_error(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+    }
+    Ok(checks)
+}
+
+/// Sleeps for `duration` unless that would pass `deadline`.
+fn sleep_until(duration: Duration, deadline: Instant) -> Result<(), Error> {
+    if Instant::now() + duration >= deadline {
+        return Err(Error::ConfirmExpired);
+    }
+    thread::sleep(duration);
+    Ok(())
+}
+
+/// Lets the next RPCs of `netconf_session` wait no longer than until `deadline`, as
+/// each RPC waits for its timeout on its own.
+pub fn limit_timeouts(netconf_session: &mut NETCONFClient, deadline: Instant) {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    let cap = |timeout: Option<Duration>| Some(timeout.map_or(remaining, |timeout| timeout.min(remaining)));
+    netconf_session.set_timeout(cap(netconf_session.timeout()));
+    netconf_session.set_commit_timeout(cap(netconf_session.commit_timeout()));
+}
+
+/// Runs all checks, printing the result of each, and returns the number of failures.
+///
+/// Fails once `deadline` passes, after which the router rolls the commit back anyway.
+pub fn run(netconf_session: &mut NETCONFClient, checks: &HealthChecks, deadline: Instant) -> Result<usize, Error> {
+    if checks.settle > 0 {
+        let settle = Duration::from_secs(checks.settle);
+        if Instant::now() + settle >= deadline {
+            return Err(Error::ConfirmExpired);
+        }
+        eprintln!("Waiting {}s before running health checks...", checks.settle);
+        thread::sleep(settle);
+    }
+
+    let mut failed = 0;
+    for check in &checks.checks {
+        if Instant::now() >= deadline {
+            return Err(Error::ConfirmExpired);
+        }
+        limit_timeouts(netconf_session, deadline);
+        let mut result = check.run_once(netconf_session);
+        for _ in 0..check.retries {
+            if result.is_ok() {
+                break;
+            }
+            sleep_until(Duration::from_secs(check.interval), deadline)?;
+            limit_timeouts(netconf_session, deadline);
+            result = check.run_once(netconf_session);
+        }
+        match result {
+            Ok(()) => eprintln!("PASS {}", check.name()),
+            Err(reason) => {
+                eprintln!("FAIL {}: {}", check.name(), reason);
+                failed += 1;
+            }
+        }
+    }
+    Ok(failed)
+}
@@ -1,2 +1,8 @@
+pub mod diagnostic;
+pub mod error;
+pub mod lint;
 pub mod netconf;
 pub mod ssh;
+pub mod statements;
+
+pub use error::{Error, Result};
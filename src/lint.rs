@@ -0,0 +1,247 @@
+//! Offline syntax checks of configuration files, so broken files are rejected before
+//! the router is touched.
+
+use crate::statements::{self, ParseError};
+
+/// Tags Junos puts on a line of their own before the statement they apply to.
+const TAGS: &[&str] = &["replace:", "delete:", "inactive:", "protect:"];
+
+fn error(line: usize, message: impl Into<String>) -> ParseError {
+    ParseError {
+        line,
+        message: message.into(),
+    }
+}
+
+/// Guesses the format of a configuration file, `set` if its first statement is a set
+/// command and `text` otherwise.
+pub fn detect_format(source: &str) -> &'static str {
+    let first = source
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with("/*"));
+    match first.and_then(|line| line.split_whitespace().next()) {
+        Some("set" | "delete" | "deactivate" | "activate" | "rename" | "insert" | "annotate") => "set",
+        _ => "text",
+    }
+}
+
+/// Checks brace and bracket balance, quoting, comments and that every statement of a
+/// text configuration is terminated.
+fn lint_text(source: &str) -> Vec<ParseError> {
+    let mut errors = vec![];
+    // The lines of the open braces
+    let mut blocks: Vec<usize> = vec![];
+    // The lines an open bracket or comment started on
+    let mut bracket: Option<usize> = None;
+    let mut comment: Option<usize> = None;
+
+    for (index, line) in source.lines().enumerate() {
+        let number = index + 1;
+        // The last character of the line outside of quotes and comments
+        let mut last: Option<char> = None;
+        // Quotes end on the same line, Junos escapes line breaks as `\n`
+        let mut quote = false;
+        let mut escaped = false;
+        let mut previous: Option<char> = None;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            let before = previous.replace(c);
+            if comment.is_some() {
+                if c == '*' && chars.peek() == Some(&'/') {
+                    chars.next();
+                    comment = None;
+                }
+                continue;
+            }
+            if quote {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => {
+                        quote = false;
+                        last = Some(c);
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                '"' => quote = true,
+                // Within a word, like `a#b`, it is no comment
+                '#' if before.is_none_or(char::is_whitespace) => break,
+                '/' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    comment = Some(number);
+                    continue;
+                }
+                '{' => blocks.push(number),
+                '}' => {
+                    if last.is_some_and(|last| !matches!(last, ';' | '{' | '}')) {
+                        errors.push(error(number, "missing `;` before `}`"));
+                    }
+                    if blocks.pop().is_none() {
+                        errors.push(error(number, "unmatched `}`"));
+                    }
+                }
+                '[' if bracket.is_some() => errors.push(error(number, "nested `[`")),
+                '[' => bracket = Some(number),
+                ']' if bracket.take().is_none() => errors.push(error(number, "unmatched `]`")),
+                _ => {}
+            }
+            if !c.is_whitespace() {
+                last = Some(c);
+            }
+        }
+
+        if quote {
+            errors.push(error(number, "unterminated quote"));
+            continue;
+        }
+        let open = comment.is_some() || bracket.is_some();
+        let mut words = line.split_whitespace().take_while(|word| !word.starts_with('#'));
+        let tags_only = words.all(|word| TAGS.contains(&word));
+        if !open && !tags_only && last.is_some_and(|last| !matches!(last, ';' | '{' | '}')) {
+            errors.push(error(number, "statement not terminated by `;`"));
+        }
+    }
+
+    if let Some(line) = comment {
+        errors.push(error(line, "unterminated comment"));
+    }
+    if let Some(line) = bracket {
+        errors.push(error(line, "unclosed `[`"));
+    }
+    for line in blocks {
+        errors.push(error(line, "unclosed `{`"));
+    }
+    errors.sort_by_key(|error| error.line);
+    errors
+}
+
+/// Checks quoting and that every statement of a set file is a configuration command
+/// with a path.
+fn lint_set(source: &str) -> Vec<ParseError> {
+    let (statements, mut errors) = statements::parse_all(source);
+    for statement in statements {
+        if statement.arguments().iter().any(|word| word == "{" || word == "}") {
+            errors.push(error(statement.line(), "braces in a set statement"));
+        }
+    }
+    errors.sort_by_key(|error| error.line);
+    errors
+}
+
+/// Returns the syntax errors of a configuration in `format`, `text` or `set`.
+pub fn lint(source: &str, format: &str) -> Vec<ParseError> {
+    match format {
+        "set" => lint_set(source),
+        _ => lint_text(source),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(source: &str, format: &str) -> Vec<(usize, String)> {
+        lint(source, format)
+            .into_iter()
+            .map(|error| (error.line, error.message))
+            .collect()
+    }
+
+    #[test]
+    fn detects_format() {
+        assert_eq!(detect_format("set system host-name r1\n"), "set");
+        assert_eq!(detect_format("# backup\n\n/* note */\ndelete protocols ospf\n"), "set");
+        assert_eq!(detect_format("system {\n    host-name r1;\n}\n"), "text");
+        assert_eq!(detect_format("## Last commit: 2024-01-01\nversion 21.4R3;\n"), "text");
+        assert_eq!(detect_format(""), "text");
+    }
+
+    #[test]
+    fn valid_text() {
+        let source = concat!(
+            "## Last changed\n",
+            "system {\n",
+            "    host-name r1; # inline comment\n",
+            "    /* multi-line\n",
+            "       comment */\n",
+            "    login {\n",
+            "        message \"a; b { c\";\n",
+            "    }\n",
+            "}\n",
+            "policy-options {\n",
+            "    community c members [ 65000:1\n",
+            "        65000:2 ];\n",
+            "}\n",
+        );
+        assert_eq!(errors(source, "text"), []);
+    }
+
+    #[test]
+    fn unterminated_statements() {
+        let source = "system {\n    host-name r1\n    domain-name example.com; }\n}\ninterfaces { lo0 { unit 0 } }\n";
+        assert_eq!(
+            errors(source, "text"),
+            [
+                (2, "statement not terminated by `;`".to_string()),
+                (4, "unmatched `}`".to_string()),
+                (5, "missing `;` before `}`".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tags_on_their_own_line() {
+        let source = concat!(
+            "system {\n",
+            "    replace:\n",
+            "    login {\n",
+            "        message hi;\n",
+            "    }\n",
+            "    inactive: protect:\n",
+            "    ntp {\n",
+            "        server 192.0.2.1;\n",
+            "    }\n",
+            "}\n",
+            "delete: # the whole hierarchy\n",
+            "snmp;\n",
+        );
+        assert_eq!(errors(source, "text"), []);
+        assert_eq!(errors("system {\n    replace: login\n}\n", "text"), [(2, "statement not terminated by `;`".to_string())]);
+    }
+
+    #[test]
+    fn hash_within_a_word() {
+        assert_eq!(errors("system {\n    login {\n        message a#b;\n    }\n}\n", "text"), []);
+        // The brace after `r1#` opens a block, so the one of `system` stays open
+        assert_eq!(errors("system {\n    host-name r1#{\n}\n", "text"), [(1, "unclosed `{`".to_string())]);
+        assert_eq!(errors("host-name r1 #;\n", "text"), [(1, "statement not terminated by `;`".to_string())]);
+    }
+
+    #[test]
+    fn unbalanced_text() {
+        assert_eq!(errors("system {\n    host-name r1;\n", "text"), [(1, "unclosed `{`".to_string())]);
+        assert_eq!(errors("}\n", "text"), [(1, "unmatched `}`".to_string())]);
+        assert_eq!(errors("a [ b;\n", "text"), [(1, "unclosed `[`".to_string())]);
+        assert_eq!(errors("a b ];\n", "text"), [(1, "unmatched `]`".to_string())]);
+        assert_eq!(errors("a;\n/* open\n", "text"), [(2, "unterminated comment".to_string())]);
+        // The quote ends with its line, so the following lines are still checked
+        assert_eq!(errors("system {\n    host-name \"r1;\n}\n", "text"), [(2, "unterminated quote".to_string())]);
+    }
+
+    #[test]
+    fn set_statements() {
+        assert_eq!(errors("set system host-name r1\nset a \"b c\"\n", "set"), []);
+        assert_eq!(
+            errors("set system {\nshow system\nset\n", "set"),
+            [
+                (1, "braces in a set statement".to_string()),
+                (2, "unknown command \"show\", expected one of set, delete, deactivate, activate, rename, insert, annotate".to_string()),
+                (3, "set requires a statement".to_string()),
+            ]
+        );
+    }
+}
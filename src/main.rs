@@ -1,18 +1,104 @@
-use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 use std::{env, fs};
 
-use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use quick_xml::events::Event;
+use quick_xml::Writer;
 use ssh2_config::{ParseRule, SshConfig};
+use tracing::Level;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
 
-use rucli::netconf::NETCONFClient;
+use rucli::statements::{self, Statement};
+use rucli::{diagnostic, lint};
+use rucli::netconf::error::NETCONFError;
+use rucli::netconf::xml::{RPCError, RPCReplyCommand};
+use rucli::netconf::record::{Recorder, Replay};
+use rucli::netconf::transport::Transport;
+use rucli::netconf::{json, CommitOptions, NETCONFClient, Outcome, ReplyEvent, ReplyStream};
 use rucli::ssh::SSHConnection;
+use rucli::Error;
+
+mod backup;
+mod guardrails;
+mod health;
+mod shell;
+mod warnings;
+
+use health::HealthChecks;
+use warnings::WarningPolicy;
+
+/// Pause between attempts to reach the router again after a confirmed commit.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Time left before the confirm timeout for the confirming commit to reach the router.
+const CONFIRM_MARGIN: Duration = Duration::from_secs(10);
+
+const EXIT_CODES: &str = "\
+Exit codes:
+  0  success
+  1  the router rejected a request
+  2  invalid usage
+  3  the SSH connection failed
+  4  authentication failed
+  5  a local file could not be read or written
+  6  connecting or waiting for a reply timed out
+  7  health checks failed after a confirmed commit
+  8  the router could not be reached again after a confirmed commit
+  9  the configuration has syntax errors
+ 10  the guardrails refuse the change
+ 11  the confirm timeout expired before the commit could be confirmed
+ 12  the interactive shell could not read from the terminal";
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, after_help = EXIT_CODES)]
 struct Cli {
-    hostname: String,
+    #[command(flatten)]
+    connection: ConnectionArgs,
+
+    /// Writes the full XML exchange into a file, with secrets redacted
+    #[arg(long, value_name = "FILE")]
+    trace_file: Option<PathBuf>,
+
+    #[arg(long, short, action=ArgAction::SetTrue)]
+    diff: bool,
+
+    /// Fail if the router replies with a warning
+    #[arg(long, action=ArgAction::SetTrue)]
+    warnings_as_errors: bool,
+
+    /// Ignore warnings whose message contains the given text
+    #[arg(long, value_name = "PATTERN")]
+    ignore_warning: Vec<String>,
+
+    /// YAML file listing the `forbidden` and `protected` configuration hierarchies
+    /// commits must not change, in addition to /etc/rucli/guardrails.yaml
+    #[arg(long, value_name = "FILE", env = "RUCLI_GUARDRAILS")]
+    guardrails: Option<PathBuf>,
+
+    /// Commits changes to protected hierarchies, forbidden ones are still refused
+    #[arg(long, action=ArgAction::SetTrue)]
+    allow_protected: bool,
+
+    #[command(subcommand)]
+    command: Commands,
+}
+
+// Everything needed to open a NETCONF session, kept apart so a command can open
+// another session to the same router. Not a doc comment, as clap would turn it into
+// the description of the program.
+#[derive(Args, Clone)]
+struct ConnectionArgs {
+    /// The router, required by all commands except lint and a backup of other routers
+    hostname: Option<String>,
 
     #[arg(long, short)]
     user: Option<String>,
@@ -20,31 +106,122 @@ struct Cli {
     #[arg(long, short, env)]
     password: Option<String>,
 
-    #[arg(long, short, action=ArgAction::SetTrue)]
+    /// Prints SSH authentication and key exchange as well as RPC timings
+    #[arg(long, short = 'D', action=ArgAction::SetTrue)]
     debug: bool,
 
-    #[arg(long, short, action=ArgAction::SetTrue)]
-    diff: bool,
+    /// Records the session as JSON lines into a file
+    #[arg(long, value_name = "FILE")]
+    record: Option<PathBuf>,
 
-    #[command(subcommand)]
-    command: Commands,
+    /// Removes secrets from the recorded session, e.g. to share it
+    #[arg(long, action=ArgAction::SetTrue, requires = "record")]
+    redact_record: bool,
+
+    /// Plays back a recorded session instead of connecting to the router
+    #[arg(long, value_name = "FILE")]
+    replay: Option<PathBuf>,
+
+    /// Seconds to wait for the connection and authentication
+    #[arg(long, default_value_t = 30, value_name = "SECONDS")]
+    connect_timeout: u64,
+
+    /// Seconds to wait for the reply to an RPC, waits forever if omitted
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<u64>,
+
+    /// Seconds to wait for the reply to a commit, defaults to --timeout
+    #[arg(long, value_name = "SECONDS")]
+    commit_timeout: Option<u64>,
+
+    /// Seconds between SSH keepalives, 0 disables them
+    #[arg(long, default_value_t = 30, value_name = "SECONDS")]
+    keepalive: u32,
 }
 
+/// Options of the commit made by a command.
+///
+/// The commands end the session after committing, like `commit and-quit` of the shell.
+#[derive(Args)]
+struct CommitArgs {
+    /// Logged with the commit, defaults to the git commit and user when the
+    /// configuration comes from a git checkout
+    #[arg(long)]
+    comment: Option<String>,
+
+    /// Commits on both routing engines of a dual-RE chassis
+    #[arg(long, action=ArgAction::SetTrue)]
+    synchronize: bool,
+
+    /// Synchronizes even if the other routing engine is locked or has uncommitted
+    /// changes
+    #[arg(long, action=ArgAction::SetTrue)]
+    force_synchronize: bool,
+}
+
+impl CommitArgs {
+    /// Whether any option was given.
+    fn given(&self) -> bool {
+        self.comment.is_some() || self.synchronize || self.force_synchronize
+    }
+
+    /// Builds the commit options, taking the default comment from the git checkout
+    /// containing `dir` if the configuration was read from there.
+    fn options(self, dir: Option<&Path>) -> CommitOptions {
+        CommitOptions {
+            comment: self.comment.or_else(|| dir.and_then(default_comment)),
+            synchronize: self.synchronize,
+            force_synchronize: self.force_synchronize,
+        }
+    }
+}
+
+impl ConnectionArgs {
+    fn hostname(&self) -> rucli::Result<&str> {
+        self.hostname
+            .as_deref()
+            .ok_or_else(|| Error::Usage("A hostname is required".to_string()))
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 enum Format {
     Text,
     JSON,
-    // TODO: We need to get into quick-xml to find out how to handle internal XML as strings.
-    // XML
+    XML,
 }
 
 #[derive(Subcommand)]
 enum Commands {
+    #[command(flatten)]
+    Router(RouterCommands),
+
+    /// Checks the syntax of a text or set configuration file without a router
+    Lint { local_file: String },
+}
+
+// The commands run on a NETCONF session to the router. Not a doc comment, as clap
+// would turn it into the description of the program.
+#[derive(Subcommand)]
+enum RouterCommands {
     /// Executes an given command on the router
     Exec {
         #[clap(value_enum)]
         format: Format,
 
+        /// Applies a JMESPath expression to the JSON output
+        #[arg(long, short)]
+        query: Option<String>,
+
+        /// Removes the Junos `[{"data": ..}]` wrapping from the JSON output
+        #[arg(long, short, action=ArgAction::SetTrue)]
+        flatten: bool,
+
+        /// Streams the output into a file instead of holding it in memory
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+
         command: Vec<String>,
     },
 
@@ -52,147 +229,868 @@ enum Commands {
     Apply {
         local_file: String,
         confirm_timeout: Option<i32>,
+
+        /// Schedules the commit for `hh:mm[:ss]` or `yyyy-mm-dd hh:mm[:ss]`, router time
+        #[arg(
+            long,
+            value_name = "TIME",
+            value_parser = parse_at_time,
+            conflicts_with_all = ["confirm_timeout", "health_check", "auto_confirm", "persist"],
+        )]
+        at: Option<String>,
+
+        /// Runs the checks in this YAML file after the commit and confirms it only if
+        /// all of them pass
+        #[arg(long, value_name = "FILE", requires = "confirm_timeout")]
+        health_check: Option<PathBuf>,
+
+        /// Confirms the commit from a new session, proving the router is still reachable
+        #[arg(long, action=ArgAction::SetTrue, requires = "confirm_timeout")]
+        auto_confirm: bool,
+
+        /// Makes the confirmed commit confirmable and cancellable from other sessions
+        /// with this token, the commit then takes no comment or synchronize options
+        #[arg(long, value_name = "TOKEN", requires = "confirm_timeout")]
+        #[arg(conflicts_with_all = ["comment", "synchronize", "force_synchronize"])]
+        persist: Option<String>,
+
+        #[command(flatten)]
+        commit: CommitArgs,
     },
 
     /// Incrementally updates the config from the given config statements
+    ///
+    /// Accepts set, delete, deactivate, activate, rename, insert and annotate
+    /// statements separated by `;` or newlines, which are loaded all at once.
     EditConfig {
+        /// The statements, or with --file the file to read them from, `-` for stdin
         statement: String,
         confirm_timeout: Option<i32>,
+
+        /// Reads the statements from the file given as STATEMENT
+        #[arg(long, short, action=ArgAction::SetTrue)]
+        file: bool,
+
+        /// Confirms the commit from a new session, proving the router is still reachable
+        #[arg(long, action=ArgAction::SetTrue, requires = "confirm_timeout")]
+        auto_confirm: bool,
+
+        /// Makes the confirmed commit confirmable and cancellable from other sessions
+        /// with this token, the commit then takes no comment or synchronize options
+        #[arg(long, value_name = "TOKEN", requires = "confirm_timeout")]
+        #[arg(conflicts_with_all = ["comment", "synchronize", "force_synchronize"])]
+        persist: Option<String>,
+
+        #[command(flatten)]
+        commit: CommitArgs,
     },
 
     /// Confirm a previously applied configuration
-    Confirm,
+    ///
+    /// With --comment or a synchronize option the confirm is a full commit, which also
+    /// commits any other changes of the candidate.
+    Confirm {
+        /// Confirms the commit made with this persist token, otherwise a `commit check`
+        /// confirms it without committing other changes of the candidate
+        #[arg(long, value_name = "TOKEN")]
+        #[arg(conflicts_with_all = ["comment", "synchronize", "force_synchronize"])]
+        persist_id: Option<String>,
+
+        #[command(flatten)]
+        commit: CommitArgs,
+    },
+
+    /// Loads a previous configuration and commits it
+    Rollback {
+        /// The rollback to load, 1 is the configuration before the last commit
+        #[arg(default_value_t = 1)]
+        rollback: u32,
+        confirm_timeout: Option<i32>,
+
+        #[command(flatten)]
+        commit: CommitArgs,
+    },
+
+    /// Shows commits scheduled with `apply --at`
+    PendingCommits {
+        /// Cancels the scheduled commits
+        #[arg(long, action=ArgAction::SetTrue)]
+        clear: bool,
+    },
+
+    /// Cancels a confirmed commit, rolling back immediately
+    CancelCommit {
+        /// Cancels the commit made with this persist token
+        #[arg(long, value_name = "TOKEN")]
+        persist_id: String,
+    },
 
     /// Loads local configuration onto router and shows a diff
     Check { local_file: String },
+
+    /// Saves the running configuration in text and set format
+    ///
+    /// Backs up the given router as well as those of --host and --inventory, one
+    /// after another. A router which fails is reported and the others still saved.
+    Backup {
+        #[arg(long)]
+        dest: PathBuf,
+
+        /// Commit the backup into a git repository at the destination
+        #[arg(long, action=ArgAction::SetTrue)]
+        git: bool,
+
+        /// Another router to back up, can be repeated
+        #[arg(long = "host", value_name = "HOST")]
+        hosts: Vec<String>,
+
+        /// File listing the routers to back up, one per line, `#` starts a comment
+        #[arg(long, value_name = "FILE")]
+        inventory: Option<PathBuf>,
+    },
+
+    /// Opens an interactive shell on a single session
+    Shell,
+
+    /// Sends an arbitrary RPC read from a file or stdin
+    Rpc {
+        /// File containing the RPC body or a whole `<rpc>`, reads from stdin if omitted or `-`
+        file: Option<PathBuf>,
+    },
+
+    /// Prints event notifications as JSON lines until interrupted
+    Subscribe {
+        /// Event stream to subscribe to, defaults to NETCONF
+        #[arg(long)]
+        stream: Option<String>,
+
+        /// File containing a subtree filter for the notifications
+        #[arg(long)]
+        filter: Option<PathBuf>,
+
+        /// Replays the notifications since this time, e.g. 2024-01-01T00:00:00Z
+        #[arg(long, value_name = "TIME")]
+        start_time: Option<String>,
+    },
 }
 
-fn main() {
-    let cli = Cli::parse();
+fn read_local_file(path: &Path) -> rucli::Result<String> {
+    fs::read_to_string(path).map_err(|source| Error::ConfigFile {
+        path: path.to_path_buf(),
+        source,
+    })
+}
 
-    let ssh_user = match cli.user {
-        Some(user) => user,
-        None => (|| -> Option<String> {
-            let mut reader = BufReader::new(
-                File::open(Path::new(
-                    (env::var("HOME").unwrap().to_owned() + "/.ssh/config").as_str(),
-                ))
-                .ok()?,
+/// Checks the syntax of `local_file` in `format`, guessed from the content if `None`,
+/// and prints every error.
+fn lint_file(local_file: &str, format: Option<&str>) -> rucli::Result<()> {
+    let source = read_local_file(Path::new(local_file))?;
+    let format = format.unwrap_or_else(|| lint::detect_format(&source));
+    let errors = lint::lint(&source, format);
+    for error in &errors {
+        eprintln!("{}", diagnostic::render_parse_error(&source, local_file, error, 2));
+    }
+    if !errors.is_empty() {
+        return Err(Error::Lint(errors.len()));
+    }
+    Ok(())
+}
+
+/// Describes where a change comes from, `<commit> by <user>`, if `dir` is inside a
+/// git checkout.
+fn default_comment(dir: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let hash = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let user = env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string());
+    Some(format!("rucli: git {} by {}", hash, user))
+}
+
+/// Prints the commit results of a synchronized commit.
+fn print_commit_results(results: &str) {
+    if !results.is_empty() {
+        println!("{}", results);
+    }
+}
+
+/// Checks that `value` is a time Junos accepts for `commit at`, `hh:mm[:ss]` or
+/// `yyyy-mm-dd hh:mm[:ss]`.
+fn parse_at_time(value: &str) -> Result<String, String> {
+    // Each field is given with its length and highest value.
+    let numbers = |part: &str, separator: char, fields: &[(usize, u32)]| {
+        let values: Vec<&str> = part.split(separator).collect();
+        values.len() <= fields.len()
+            && values.len() >= 2
+            && values.iter().zip(fields).all(|(value, (length, max))| {
+                value.len() == *length
+                    && value.chars().all(|c| c.is_ascii_digit())
+                    && value.parse::<u32>().is_ok_and(|value| value <= *max)
+            })
+    };
+    let time = |time: &str| numbers(time, ':', &[(2, 23), (2, 59), (2, 59)]);
+    let date = |date: &str| {
+        numbers(date, '-', &[(4, 9999), (2, 12), (2, 31)])
+            && date.len() == 10
+            && date.split('-').skip(1).all(|value| value != "00")
+    };
+    let valid = match value.trim().split_once(' ') {
+        Some((d, t)) => date(d) && time(t),
+        None => time(value.trim()),
+    };
+    if !valid {
+        return Err("expected hh:mm[:ss] or yyyy-mm-dd hh:mm[:ss]".to_string());
+    }
+    Ok(value.trim().to_string())
+}
+
+/// Loads `source` into the candidate configuration, as the given `statements` if it
+/// was parsed into them. If the router rejects it, the offending lines of `source`
+/// are printed.
+fn load(
+    netconf_session: &mut NETCONFClient,
+    policy: &WarningPolicy,
+    source: &str,
+    format: &str,
+    file_name: &str,
+    statements: Option<&[Statement]>,
+) -> rucli::Result<()> {
+    let action = if format == "set" { "set" } else { "update" };
+    let config = statements.map_or_else(|| source.to_string(), statements::to_configuration_set);
+    let result = netconf_session
+        .load_configuration(config, action.into(), format.into())
+        .and_then(|outcome| policy.check(outcome));
+    let errors = match &result {
+        Err(NETCONFError::RpcError(error)) => std::slice::from_ref(&**error),
+        Err(NETCONFError::LoadErrors { errors, .. }) => errors.as_slice(),
+        _ => &[],
+    };
+    for error in errors {
+        let diagnostic = match statements {
+            Some(statements) => diagnostic::render_statements(source, statements, file_name, error, 2),
+            None => diagnostic::render(source, format, file_name, error, 2),
+        };
+        if let Some(diagnostic) = diagnostic {
+            eprintln!("{}", diagnostic);
+        }
+    }
+    result.map_err(Error::Load)
+}
+
+/// Writes a streamed reply into `out`, re-indenting XML and writing text and JSON as is.
+///
+/// Text arrives in pieces, so whitespace is held back until it turns out not to be
+/// the whole text node.
+fn write_reply(mut stream: ReplyStream, format: Format, out: impl Write) -> rucli::Result<Vec<RPCError>> {
+    let mut warnings = vec![];
+    let mut writer = Writer::new_with_indent(out, b' ', 2);
+    let mut whitespace = vec![];
+    while let Some(event) = stream.next_event()? {
+        match event {
+            ReplyEvent::Command(RPCReplyCommand::RPCError(warning)) => warnings.push(warning),
+            ReplyEvent::Command(_) => {}
+            ReplyEvent::Event(Event::Text(text)) if text.iter().all(u8::is_ascii_whitespace) => {
+                whitespace.extend_from_slice(&text);
+            }
+            ReplyEvent::Event(Event::Text(text)) => {
+                writer.get_mut().write_all(&whitespace)?;
+                whitespace.clear();
+                match format {
+                    Format::XML => writer.write_event(Event::Text(text)).map_err(NETCONFError::from)?,
+                    _ => {
+                        let text = text.unescape().map_err(NETCONFError::from)?;
+                        writer.get_mut().write_all(text.as_bytes())?;
+                    }
+                }
+            }
+            ReplyEvent::Event(event) => {
+                whitespace.clear();
+                if format == Format::XML {
+                    writer.write_event(event).map_err(NETCONFError::from)?;
+                }
+            }
+        }
+    }
+    let mut out = writer.into_inner();
+    out.write_all(b"\n")?;
+    out.flush()?;
+    Ok(warnings)
+}
+
+/// Runs `f` with the configuration locked. On failure the candidate is discarded
+/// before unlocking, so a failed change does not linger on the router.
+fn with_lock<T>(
+    netconf_session: &mut NETCONFClient,
+    policy: &WarningPolicy,
+    f: impl FnOnce(&mut NETCONFClient) -> rucli::Result<T>,
+) -> rucli::Result<T> {
+    let locked = netconf_session.lock_configuration()?;
+    if let Err(e) = policy.check(locked) {
+        if let Err(unlock_error) = netconf_session.unlock_configuration() {
+            eprintln!("Unlocking configuration failed: {}", unlock_error);
+        }
+        return Err(e.into());
+    }
+    match f(netconf_session) {
+        Ok(value) => {
+            policy.check(netconf_session.unlock_configuration()?)?;
+            Ok(value)
+        }
+        Err(e) => {
+            if let Err(discard_error) = netconf_session.discard_changes() {
+                eprintln!("Discarding changes failed: {}", discard_error);
+            }
+            if let Err(unlock_error) = netconf_session.unlock_configuration() {
+                eprintln!("Unlocking configuration failed: {}", unlock_error);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Logs RPC timings to stderr with `--debug`, and everything including the XML
+/// of each message to the `--trace-file`.
+fn init_tracing(debug: bool, trace_file: Option<&Path>) -> rucli::Result<()> {
+    let stderr = debug.then(|| {
+        tracing_subscriber::fmt::layer()
+            .with_writer(io::stderr)
+            .with_filter(Targets::new().with_target("rucli", Level::DEBUG))
+    });
+    let file = match trace_file {
+        Some(path) => {
+            let file = File::create(path).map_err(|source| Error::ConfigFile {
+                path: path.to_path_buf(),
+                source,
+            })?;
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .with_writer(Mutex::new(file))
+                    .with_ansi(false)
+                    .with_filter(Targets::new().with_target("rucli", Level::TRACE)),
+            )
+        }
+        None => None,
+    };
+    tracing_subscriber::registry().with(stderr).with(file).init();
+    Ok(())
+}
+
+/// Opens the transport to the router, or to the recorded session with `--replay`.
+/// With `--record`, the session is written to the given file, after the sessions
+/// recorded before if `append` is set.
+fn connect(cli: &ConnectionArgs, append: bool, connect_timeout: Duration) -> rucli::Result<Box<dyn Transport>> {
+    let transport: Box<dyn Transport> = match &cli.replay {
+        Some(path) => {
+            let replay = File::open(path)
+                .and_then(|file| Replay::from_reader(BufReader::new(file)))
+                .map_err(|source| Error::ConfigFile {
+                    path: path.clone(),
+                    source,
+                })?;
+            Box::new(replay)
+        }
+        None => {
+            let hostname = cli.hostname()?;
+            let ssh_user = match cli.user.clone() {
+                Some(user) => user,
+                None => (|| -> Option<String> {
+                    let mut reader = BufReader::new(
+                        File::open(Path::new(
+                            (env::var("HOME").ok()? + "/.ssh/config").as_str(),
+                        ))
+                        .ok()?,
+                    );
+                    let config = SshConfig::default()
+                        .parse(&mut reader, ParseRule::STRICT)
+                        .ok()?;
+                    let params = config.query(hostname);
+
+                    params.user
+                })()
+                .or_else(|| env::var("USER").ok())
+                .ok_or_else(|| Error::Usage("Cannot determine the SSH user, please pass --user".to_string()))?,
+            };
+
+            let mut ssh_connection = SSHConnection::new(
+                ssh_user.as_str(),
+                cli.password.clone(),
+                if hostname.contains(':') && !hostname.starts_with('[') {
+                    format!("[{}]:830", hostname)
+                } else {
+                    format!("{}:830", hostname)
+                }
+                .as_str(),
+                cli.debug,
             );
-            let config = SshConfig::default()
-                .parse(&mut reader, ParseRule::STRICT)
-                .ok()?;
-            let params = config.query(&cli.hostname);
-
-            params.user
-        })()
-        .unwrap_or(env::var("USER").unwrap()),
+            ssh_connection.connect_timeout = Some(connect_timeout);
+            ssh_connection.keepalive_interval = cli.keepalive;
+            ssh_connection.connect()?;
+
+            Box::new(ssh_connection.transport()?)
+        }
+    };
+    match &cli.record {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(append)
+                .truncate(!append)
+                .open(path);
+            let file = file.map_err(|source| Error::ConfigFile {
+                path: path.clone(),
+                source,
+            })?;
+            let recorder = Recorder::new(transport, BufWriter::new(file));
+            match cli.redact_record {
+                true => Ok(Box::new(recorder.redacted())),
+                false => Ok(Box::new(recorder)),
+            }
+        }
+        None => Ok(transport),
+    }
+}
+
+/// Connects to the router and exchanges the hello.
+///
+/// With a `deadline`, connecting times out by then at the latest and the timeouts of the
+/// session are capped to the time left. Each RPC waits that long on its own, so they
+/// are to be capped again with [`health::limit_timeouts`] before later RPCs.
+fn open_session(cli: &ConnectionArgs, append: bool, deadline: Option<Instant>) -> rucli::Result<NETCONFClient> {
+    let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+    let cap = |timeout: Option<Duration>| match (timeout, remaining) {
+        (Some(timeout), Some(remaining)) => Some(timeout.min(remaining)),
+        (timeout, remaining) => timeout.or(remaining),
     };
+    let connect_timeout = cap(Some(Duration::from_secs(cli.connect_timeout))).unwrap_or_default();
 
-    let mut ssh_connection = SSHConnection::new(
-        ssh_user.as_str(),
-        cli.password,
-        format!("{}:830", cli.hostname).as_str(),
-        cli.debug,
-    );
-    ssh_connection.connect().unwrap();
+    let mut netconf_session = NETCONFClient::new(connect(cli, append, connect_timeout)?);
+    netconf_session.set_timeout(cap(cli.timeout.map(Duration::from_secs)));
+    netconf_session.set_commit_timeout(cap(cli.commit_timeout.map(Duration::from_secs)));
+    netconf_session.init()?;
+    Ok(netconf_session)
+}
 
-    let mut netconf_session = NETCONFClient::new(ssh_connection.channel.expect(""));
-    netconf_session.init().unwrap();
+/// The time until which a commit made at `committed` can still be confirmed.
+fn confirm_deadline(committed: Instant, confirm_timeout: i32) -> Instant {
+    committed + Duration::from_secs(confirm_timeout.max(1) as u64 * 60) - CONFIRM_MARGIN
+}
+
+/// Fails if `deadline` has passed, as confirming then would be a plain commit of
+/// whatever the router rolled back to.
+fn check_deadline(deadline: Instant) -> rucli::Result<()> {
+    if Instant::now() >= deadline {
+        return Err(Error::ConfirmExpired);
+    }
+    Ok(())
+}
+
+/// Confirms a commit confirmed from a new session, which proves that the change did
+/// not cut off management access. The health checks, if any, run on that session first.
+///
+/// Connecting is retried until the `deadline` for the confirm is about to pass, and
+/// nothing is confirmed after it.
+fn confirm_from_new_session(
+    connection: &ConnectionArgs,
+    policy: &WarningPolicy,
+    deadline: Instant,
+    persist: Option<String>,
+    health_checks: Option<&HealthChecks>,
+) -> rucli::Result<()> {
+    eprintln!("Reconnecting to verify the router is still reachable...");
+    let mut netconf_session = loop {
+        check_deadline(deadline)?;
+        match open_session(connection, true, Some(deadline)) {
+            Ok(netconf_session) => break netconf_session,
+            Err(e @ (Error::Ssh(_) | Error::Netconf(_))) if Instant::now() + RECONNECT_INTERVAL < deadline => {
+                eprintln!("Reconnecting failed: {}", e);
+                thread::sleep(RECONNECT_INTERVAL);
+            }
+            Err(e) => return Err(Error::Unreachable(Box::new(e))),
+        }
+    };
+    check_deadline(deadline)?;
+
+    if let Some(health_checks) = health_checks {
+        let failed = health::run(&mut netconf_session, health_checks, deadline)?;
+        if failed > 0 {
+            return Err(Error::HealthCheck(failed));
+        }
+    }
+    check_deadline(deadline)?;
+    health::limit_timeouts(&mut netconf_session, deadline);
+    eprintln!("Confirming configuration from the new session");
+    print_commit_results(&policy.check(netconf_session.confirm_configuration(persist)?)?);
+    Ok(())
+}
+
+/// Backs up the configuration of every router, each on its own session, and commits
+/// all of them at once. Fails with the first error after saving the other routers.
+fn backup_all(
+    connection: &ConnectionArgs,
+    policy: &WarningPolicy,
+    dest: &Path,
+    git: bool,
+    mut hosts: Vec<String>,
+    inventory: Option<&Path>,
+) -> rucli::Result<()> {
+    if let Some(hostname) = &connection.hostname {
+        hosts.insert(0, hostname.clone());
+    }
+    if let Some(inventory) = inventory {
+        hosts.extend(backup::parse_inventory(&read_local_file(inventory)?));
+    }
+    if hosts.is_empty() {
+        return Err(Error::Usage("A hostname, --host or --inventory is required".to_string()));
+    }
+
+    let mut first_error = None;
+    for (i, hostname) in hosts.iter().enumerate() {
+        let connection = ConnectionArgs {
+            hostname: Some(hostname.clone()),
+            ..connection.clone()
+        };
+        let saved = open_session(&connection, i > 0, None).and_then(|mut netconf_session| {
+            let text = policy.check(netconf_session.get_configuration("text".to_string())?)?;
+            let set = policy.check(netconf_session.get_configuration("set".to_string())?)?;
+            backup::write(dest, hostname, &text, &set)?;
+            Ok(())
+        });
+        if let Err(e) = saved {
+            eprintln!("{}: backup failed: {}", hostname, e);
+            first_error.get_or_insert(e);
+        }
+    }
+
+    if git {
+        let devices = backup::commit(dest)?;
+        if devices.is_empty() {
+            eprintln!("No configuration changes");
+        } else {
+            eprintln!("Committed changes for {}", devices.join(", "));
+        }
+    }
+    first_error.map_or(Ok(()), Err)
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+fn run(cli: Cli) -> rucli::Result<()> {
+    init_tracing(cli.connection.debug, cli.trace_file.as_deref())?;
+
+    let command = match cli.command {
+        Commands::Lint { local_file } => {
+            lint_file(&local_file, None)?;
+            eprintln!("{}: no syntax errors", local_file);
+            return Ok(());
+        }
+        Commands::Router(command) => command,
+    };
+    // Broken files are rejected before the configuration is locked.
+    if let RouterCommands::Apply { local_file, .. } | RouterCommands::Check { local_file } = &command {
+        lint_file(local_file, Some("text"))?;
+    }
+
+    let guardrails = guardrails::load_all(cli.guardrails.as_deref())?;
+    // Refuses a commit if the diff touches a guarded hierarchy, which discards the
+    // candidate in `with_lock`.
+    let check_guardrails = |diff: &str| match &guardrails {
+        Some(guardrails) => guardrails.check(diff, cli.allow_protected),
+        None => Ok(()),
+    };
+
+    let policy = WarningPolicy {
+        as_errors: cli.warnings_as_errors,
+        ignore: cli.ignore_warning,
+    };
+
+    if let RouterCommands::Backup { dest, git, hosts, inventory } = command {
+        return backup_all(&cli.connection, &policy, &dest, git, hosts, inventory.as_deref());
+    }
+
+    let mut netconf_session = open_session(&cli.connection, false, None)?;
+
+    match command {
+        RouterCommands::Exec {
+            format,
+            query,
+            flatten,
+            output,
+            command,
+        } => {
+            if (query.is_some() || flatten) && format != Format::JSON {
+                return Err(Error::Usage("--query and --flatten require the json format".to_string()));
+            }
+            if (query.is_some() || flatten) && output.is_some() {
+                return Err(Error::Usage("--output cannot be combined with --query or --flatten".to_string()));
+            }
 
-    match cli.command {
-        Commands::Exec { format, command } => {
             let format_str = match format {
                 Format::Text => "text",
                 Format::JSON => "json",
+                Format::XML => "xml",
             };
 
             let command_str = command.join(" ").to_owned();
 
-            let r = netconf_session
-                .send_command(command_str, format_str.to_owned())
-                .unwrap();
+            if let Some(output) = output {
+                let file = BufWriter::new(File::create(output)?);
+                let stream = netconf_session.stream_command(command_str, format_str.to_owned())?;
+                let warnings = write_reply(stream, format, file)?;
+                return Ok(policy.check(Outcome { value: (), warnings })?);
+            }
+
+            let r = policy.check(netconf_session.send_command(command_str, format_str.to_owned())?)?;
 
-            println!("{}", r);
+            if query.is_none() && !flatten {
+                println!("{}", r);
+                return Ok(());
+            }
+
+            let mut value: serde_json::Value = serde_json::from_str(&r)?;
+            if flatten {
+                value = json::flatten(value);
+            }
+            if let Some(query) = query {
+                let expression = jmespath::compile(&query)
+                    .map_err(|e| Error::Usage(format!("Invalid query: {}", e)))?;
+                let result = expression
+                    .search(value)
+                    .map_err(|e| Error::Usage(format!("Query failed: {}", e)))?;
+                value = serde_json::to_value(result)?;
+            }
+            println!("{}", serde_json::to_string_pretty(&value)?);
         }
-        Commands::Apply {
+        RouterCommands::Apply {
             local_file,
             confirm_timeout,
+            at,
+            health_check,
+            auto_confirm,
+            persist,
+            commit,
         } => {
-            let data = fs::read_to_string(local_file).unwrap();
+            let data = read_local_file(Path::new(&local_file))?;
+            let dir = Path::new(&local_file).parent().filter(|dir| !dir.as_os_str().is_empty());
+            let options = commit.options(Some(dir.unwrap_or(Path::new("."))));
+            let health_checks = health_check.as_deref().map(health::load).transpose()?;
 
-            netconf_session.lock_configuration().unwrap();
+            let committed = with_lock(&mut netconf_session, &policy, |netconf_session| {
+                load(netconf_session, &policy, &data, "text", &local_file, None)?;
 
-            if let Err(e) = netconf_session.load_configuration(data, "update".into(), "text".into()) {
-                eprintln!("Config load failed: {}", e);
-                std::process::exit(1);
-            }
+                let diff_reply = policy.check(netconf_session.diff_configuration("text".to_string())?)?;
+                if cli.diff {
+                    println!("{}", diff_reply);
+                }
+                check_guardrails(&diff_reply)?;
 
-            let diff_reply = netconf_session
-                .diff_configuration("text".to_string())
-                .unwrap();
-            if cli.diff {
-                println!("{}", diff_reply);
-            }
+                if let Some(at) = at {
+                    eprintln!("Scheduling commit at {}...", at);
+                    print_commit_results(&policy.check(netconf_session.schedule_commit(at, &options)?)?);
+                    return Ok(Instant::now());
+                }
 
-            eprintln!("Applying configuration...");
+                eprintln!("Applying configuration...");
 
-            netconf_session
-                .apply_configuration(confirm_timeout)
-                .unwrap();
+                let committed = Instant::now();
+                print_commit_results(&policy.check(netconf_session.apply_configuration(confirm_timeout, persist.clone(), &options)?)?);
+                Ok(committed)
+            })?;
 
-            netconf_session.unlock_configuration().unwrap();
+            let deadline = confirm_timeout.map(|confirm_timeout| confirm_deadline(committed, confirm_timeout));
+            if let (true, Some(deadline)) = (auto_confirm, deadline) {
+                confirm_from_new_session(&cli.connection, &policy, deadline, persist, health_checks.as_ref())?;
+            } else if let (Some(health_checks), Some(deadline)) = (health_checks, deadline) {
+                let failed = health::run(&mut netconf_session, &health_checks, deadline)?;
+                if failed > 0 {
+                    return Err(Error::HealthCheck(failed));
+                }
+                check_deadline(deadline)?;
+                health::limit_timeouts(&mut netconf_session, deadline);
+                eprintln!("All health checks passed, confirming configuration");
+                print_commit_results(&policy.check(netconf_session.confirm_configuration(persist)?)?);
+            }
         }
-        Commands::EditConfig {
+        RouterCommands::EditConfig {
             statement,
             confirm_timeout,
+            file,
+            auto_confirm,
+            persist,
+            commit,
         } => {
-            netconf_session.lock_configuration().unwrap();
-
-            for line in statement.split(";") {
-                if let Err(e) = netconf_session.load_configuration(line.into(), "set".into(), "set".into()) {
-                    eprintln!("Config load failed: {}", e);
-                    std::process::exit(1);
+            let (source, name, dir) = match file {
+                false => (statement, "<statement>".to_string(), None),
+                true if statement == "-" => {
+                    let mut source = String::new();
+                    io::stdin().read_to_string(&mut source)?;
+                    (source, "<stdin>".to_string(), None)
                 }
+                true => {
+                    let path = Path::new(&statement);
+                    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+                    (read_local_file(path)?, statement.clone(), Some(dir.unwrap_or(Path::new("."))))
+                }
+            };
+            let statements = statements::parse(&source).map_err(|e| Error::Usage(format!("{}: {}", name, e)))?;
+            if statements.is_empty() {
+                return Err(Error::Usage(format!("{}: no statements", name)));
             }
+            let options = commit.options(dir);
 
-            let diff_reply = netconf_session
-                .diff_configuration("text".to_string())
-                .unwrap();
-            if cli.diff {
-                println!("{}", diff_reply);
-            }
+            let committed = with_lock(&mut netconf_session, &policy, |netconf_session| {
+                load(netconf_session, &policy, &source, "set", &name, Some(&statements))?;
 
-            eprintln!("Applying configuration...");
+                let diff_reply = policy.check(netconf_session.diff_configuration("text".to_string())?)?;
+                if cli.diff {
+                    println!("{}", diff_reply);
+                }
+                check_guardrails(&diff_reply)?;
+
+                eprintln!("Applying configuration...");
 
-            netconf_session
-                .apply_configuration(confirm_timeout)
-                .unwrap();
+                let committed = Instant::now();
+                print_commit_results(&policy.check(netconf_session.apply_configuration(confirm_timeout, persist.clone(), &options)?)?);
+                Ok(committed)
+            })?;
 
-            netconf_session.unlock_configuration().unwrap();
+            if let (true, Some(confirm_timeout)) = (auto_confirm, confirm_timeout) {
+                let deadline = confirm_deadline(committed, confirm_timeout);
+                confirm_from_new_session(&cli.connection, &policy, deadline, persist, None)?;
+            }
         }
-        Commands::Confirm => {
+        RouterCommands::Confirm { persist_id, commit } => {
+            // Confirming with a persist token or commit options commits the candidate as
+            // well, otherwise the confirm is a `commit check` which leaves it alone.
+            if guardrails.is_some() && (persist_id.is_some() || commit.given()) {
+                let diff_reply = policy.check(netconf_session.diff_configuration("text".to_string())?)?;
+                check_guardrails(&diff_reply)?;
+            }
             eprintln!("Confirming configuration");
 
-            netconf_session.confirm_configuration().unwrap();
+            let reply = if commit.given() {
+                netconf_session.apply_configuration(None, None, &commit.options(None))?
+            } else {
+                netconf_session.confirm_configuration(persist_id)?
+            };
+            print_commit_results(&policy.check(reply)?);
+        }
+        RouterCommands::Rollback {
+            rollback,
+            confirm_timeout,
+            commit,
+        } => {
+            let options = commit.options(None);
+            with_lock(&mut netconf_session, &policy, |netconf_session| {
+                policy.check(netconf_session.rollback_configuration(rollback)?)?;
+
+                let diff_reply = policy.check(netconf_session.diff_configuration("text".to_string())?)?;
+                if cli.diff {
+                    println!("{}", diff_reply);
+                }
+                check_guardrails(&diff_reply)?;
+
+                eprintln!("Applying rollback {}...", rollback);
+
+                print_commit_results(&policy.check(netconf_session.apply_configuration(confirm_timeout, None, &options)?)?);
+                Ok(())
+            })?;
+        }
+        RouterCommands::PendingCommits { clear } => {
+            if clear {
+                let output = policy.check(netconf_session.send_command("clear system commit".into(), "text".into())?)?;
+                println!("{}", output.trim());
+                return Ok(());
+            }
+
+            let information = policy.check(netconf_session.get_commit_information()?)?;
+            if information.pending.is_empty() {
+                println!("No pending commits");
+            }
+            for commit in information.pending {
+                println!("{}", commit);
+            }
         }
-        Commands::Check { local_file } => {
-            let data = fs::read_to_string(local_file).unwrap();
+        RouterCommands::CancelCommit { persist_id } => {
+            eprintln!("Cancelling commit");
 
-            let _ = netconf_session.lock_configuration().unwrap();
+            policy.check(netconf_session.cancel_commit(Some(persist_id))?)?;
+        }
+        RouterCommands::Check { local_file } => {
+            let data = read_local_file(Path::new(&local_file))?;
+
+            with_lock(&mut netconf_session, &policy, |netconf_session| {
+                load(netconf_session, &policy, &data, "text", &local_file, None)?;
+
+                let diff_reply = policy.check(netconf_session.diff_configuration("text".to_string())?)?;
+                if cli.diff {
+                    println!("{}", diff_reply);
+                }
+                Ok(())
+            })?;
+        }
+        RouterCommands::Backup { .. } => unreachable!("backups run on their own sessions"),
+        RouterCommands::Shell => {
+            shell::run(&mut netconf_session, cli.connection.hostname()?, &policy, guardrails.as_ref(), cli.allow_protected)
+                .map_err(|e| Error::Shell(io::Error::other(e)))?;
+        }
+        RouterCommands::Rpc { file } => {
+            let body = match file {
+                Some(file) if file.as_os_str() != "-" => read_local_file(&file)?,
+                _ => {
+                    let mut body = String::new();
+                    io::stdin().read_to_string(&mut body)?;
+                    body
+                }
+            };
 
-            if let Err(e) = netconf_session.load_configuration(data, "update".into(), "text".into()) {
-                eprintln!("Config load failed: {}", e);
-                std::process::exit(1);
+            if let Some(guardrails) = &guardrails {
+                guardrails.check_rpc(&body)?;
             }
+            println!("{}", policy.check(netconf_session.send_raw_rpc(&body)?)?);
+        }
+        RouterCommands::Subscribe {
+            stream,
+            filter,
+            start_time,
+        } => {
+            let filter = filter.map(|filter| read_local_file(&filter)).transpose()?;
+            policy.check(netconf_session.create_subscription(
+                stream.as_deref(),
+                filter.as_deref(),
+                start_time.as_deref(),
+            )?)?;
 
-            let diff_reply = netconf_session
-                .diff_configuration("text".to_string())
-                .unwrap();
-            if cli.diff {
-                println!("{}", diff_reply);
+            let mut stdout = io::stdout().lock();
+            loop {
+                let notification = netconf_session.next_notification()?;
+                let value = match json::from_xml(&notification.xml).map_err(NETCONFError::from)? {
+                    serde_json::Value::Object(mut root) => root.remove("notification").unwrap_or_default(),
+                    other => other,
+                };
+                writeln!(stdout, "{}", value)?;
+                stdout.flush()?;
             }
-            let _ = netconf_session.unlock_configuration().unwrap();
         }
     }
+
+    Ok(())
 }
@@ -0,0 +1,353 @@
+//! A NETCONF client for tokio, available with the `async` feature.
+//!
+//! It offers the same RPCs as [`NETCONFClient`](super::NETCONFClient), generated from
+//! the same definition, on top of any [`AsyncRead`] + [`AsyncWrite`] stream.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use quick_xml::de::from_str;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, ReadBuf};
+use tokio::time::{Instant, Sleep};
+
+use super::client::impl_client;
+use super::error::{NETCONFError, NETCONFResult};
+use super::framer::Framer;
+use super::message::{self, Pipeline, Reply, PIPELINE_DEPTH};
+use super::stream::{text_event, text_length, ParseState, Step};
+use super::xml::{self, CommitInformation, Hello, Notification, RPCCommand};
+use super::{CommitOptions, Outcome, ReplyEvent};
+
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// A byte stream carrying NETCONF messages, e.g. the pipes of an `ssh` process.
+pub trait AsyncTransport: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> AsyncTransport for T {}
+
+pub struct AsyncNETCONFClient {
+    transport: Box<dyn AsyncTransport>,
+    framer: Framer,
+    read_buffer: Vec<u8>,
+    /// Set if an [`AsyncReplyStream`] was dropped before the end of its message
+    unfinished_message: bool,
+    message_id: u64,
+    /// Notifications received while waiting for an `rpc-reply`
+    notifications: VecDeque<String>,
+    timeout: Option<Duration>,
+    commit_timeout: Option<Duration>,
+}
+
+impl AsyncNETCONFClient {
+    pub fn new(transport: impl AsyncTransport + 'static) -> AsyncNETCONFClient {
+        AsyncNETCONFClient {
+            transport: Box::new(transport),
+            framer: Framer::new(),
+            read_buffer: vec![0u8; READ_BUFFER_SIZE],
+            unfinished_message: false,
+            message_id: 0,
+            notifications: VecDeque::new(),
+            timeout: None,
+            commit_timeout: None,
+        }
+    }
+
+    /// Reads the next bytes from the transport into the framer.
+    async fn fill(&mut self, deadline: Option<Instant>) -> NETCONFResult<()> {
+        let read = self.transport.read(&mut self.read_buffer);
+        let result = match (deadline, self.timeout) {
+            (Some(deadline), Some(timeout)) => tokio::time::timeout_at(deadline, read)
+                .await
+                .map_err(|_| NETCONFError::Timeout(timeout))?,
+            _ => read.await,
+        };
+        match result? {
+            0 => Err(NETCONFError::ConnectionClosed),
+            bytes => {
+                self.framer.push(&self.read_buffer[..bytes]);
+                Ok(())
+            }
+        }
+    }
+
+    async fn write(&mut self, payload: &[u8]) -> NETCONFResult<()> {
+        self.transport.write_all(payload).await?;
+        self.transport.flush().await?;
+        Ok(())
+    }
+}
+
+impl_client!(AsyncNETCONFClient, AsyncReplyStream, async, await);
+
+/// Reads the body of the current message from the transport, ending at the delimiter.
+struct MessageReader<'a> {
+    client: &'a mut AsyncNETCONFClient,
+    deadline: Option<Pin<Box<Sleep>>>,
+    pending: Vec<u8>,
+    position: usize,
+    done: bool,
+    /// The original error, as quick-xml only passes on an `io::Error`
+    error: Option<NETCONFError>,
+    message_id: u64,
+    sent: Instant,
+    bytes: usize,
+    /// The message so far, kept only to be traced
+    traced: Option<Vec<u8>>,
+}
+
+impl MessageReader<'_> {
+    fn fail(&mut self, error: NETCONFError) -> Poll<io::Result<()>> {
+        let io_error = io::Error::other(error.to_string());
+        self.error = Some(error);
+        Poll::Ready(Err(io_error))
+    }
+
+    fn log(&mut self) {
+        let xml = self.traced.take().map(|xml| String::from_utf8_lossy(&xml).into_owned());
+        message::log_streamed(Some(self.message_id), self.bytes, xml.as_deref(), Some(self.sent.elapsed()));
+    }
+}
+
+impl AsyncRead for MessageReader<'_> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        while this.position == this.pending.len() {
+            if this.done {
+                return Poll::Ready(Ok(()));
+            }
+            let (data, end) = this.client.framer.take_partial();
+            this.bytes += data.len();
+            if let Some(traced) = &mut this.traced {
+                traced.extend_from_slice(&data);
+            }
+            this.pending = data;
+            this.position = 0;
+            this.done = end;
+            if end {
+                this.log();
+            }
+            if this.pending.is_empty() && !end {
+                if let Some(deadline) = &mut this.deadline {
+                    if deadline.as_mut().poll(cx).is_ready() {
+                        let timeout = this.client.timeout.unwrap_or_default();
+                        return this.fail(NETCONFError::Timeout(timeout));
+                    }
+                }
+                let client = &mut *this.client;
+                let mut read_buf = ReadBuf::new(&mut client.read_buffer);
+                if let Err(e) = ready!(Pin::new(&mut client.transport).poll_read(cx, &mut read_buf)) {
+                    return this.fail(e.into());
+                }
+                let bytes = read_buf.filled().len();
+                if bytes == 0 {
+                    return this.fail(NETCONFError::ConnectionClosed);
+                }
+                client.framer.push(&client.read_buffer[..bytes]);
+            }
+        }
+        let bytes = buf.remaining().min(this.pending.len() - this.position);
+        buf.put_slice(&this.pending[this.position..this.position + bytes]);
+        this.position += bytes;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for MessageReader<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.client.unfinished_message = true;
+        }
+    }
+}
+
+/// The reply to an RPC, parsed while it is received.
+///
+/// See [`ReplyStream`](super::ReplyStream), which this mirrors.
+pub struct AsyncReplyStream<'a> {
+    reader: Reader<BufReader<MessageReader<'a>>>,
+    buffer: Vec<u8>,
+    state: ParseState,
+    /// The length of the text yielded last, still in the read buffer
+    consumed: usize,
+    /// An entity or a character which was split between two reads
+    partial: Vec<u8>,
+}
+
+impl<'a> AsyncReplyStream<'a> {
+    fn new(client: &'a mut AsyncNETCONFClient, message_id: u64, sent: Instant) -> AsyncReplyStream<'a> {
+        let deadline = client.deadline().map(|deadline| Box::pin(tokio::time::sleep_until(deadline)));
+        let message = MessageReader {
+            client,
+            deadline,
+            pending: vec![],
+            position: 0,
+            done: false,
+            error: None,
+            message_id,
+            sent,
+            bytes: 0,
+            traced: message::wire_traced().then(Vec::new),
+        };
+        AsyncReplyStream {
+            reader: Reader::from_reader(BufReader::new(message)),
+            buffer: vec![],
+            state: ParseState::new(message_id),
+            consumed: 0,
+            partial: vec![],
+        }
+    }
+
+    fn take_error(&mut self, error: quick_xml::Error) -> NETCONFError {
+        match self.reader.get_mut().get_mut().error.take() {
+            Some(error) => error,
+            None => error.into(),
+        }
+    }
+
+    async fn fill_buf(&mut self) -> NETCONFResult<&[u8]> {
+        if let Err(e) = self.reader.get_mut().fill_buf().await {
+            return Err(self.take_error(e.into()));
+        }
+        Ok(self.reader.get_ref().buffer())
+    }
+
+    /// Reads an entity or a character which was split at the end of the read buffer
+    /// into `partial`.
+    async fn read_partial(&mut self) -> NETCONFResult<()> {
+        self.partial.clear();
+        while text_length(&self.partial) < self.partial.len() || self.partial.is_empty() {
+            match self.fill_buf().await?.first() {
+                Some(&byte) if byte != b'<' => self.partial.push(byte),
+                _ => break,
+            }
+            self.reader.get_mut().consume(1);
+        }
+        Ok(())
+    }
+
+    /// Reads the next tag, which quick-xml only gets to see once the text before it
+    /// was consumed.
+    async fn read_event(&mut self) -> NETCONFResult<Event<'static>> {
+        self.buffer.clear();
+        match self.reader.read_event_into_async(&mut self.buffer).await {
+            Ok(event) => Ok(event.into_owned()),
+            Err(e) => Err(self.take_error(e)),
+        }
+    }
+
+    async fn finish(&mut self) -> NETCONFResult<()> {
+        let result = tokio::io::copy(self.reader.get_mut().get_mut(), &mut tokio::io::sink()).await;
+        if let Err(e) = result {
+            return Err(self.take_error(e.into()));
+        }
+        Ok(())
+    }
+
+    /// Returns the next item of the reply, `None` after the end of `rpc-reply`.
+    pub async fn next_event(&mut self) -> NETCONFResult<Option<ReplyEvent<'_>>> {
+        let consumed = std::mem::take(&mut self.consumed);
+        self.reader.get_mut().consume(consumed);
+        loop {
+            if self.state.finished {
+                return Ok(None);
+            }
+            let length = match self.fill_buf().await? {
+                [] | [b'<', ..] => None,
+                buffered => Some(text_length(buffered)),
+            };
+            match length {
+                None => {
+                    let event = self.read_event().await?;
+                    match self.state.step(event)? {
+                        Step::Item(item) => return Ok(Some(item)),
+                        Step::Continue => {}
+                        Step::End => {
+                            self.finish().await?;
+                            return Ok(None);
+                        }
+                    }
+                }
+                Some(0) => {
+                    self.read_partial().await?;
+                    if self.state.text(&self.partial) {
+                        return Ok(Some(ReplyEvent::Event(text_event(&self.partial)?)));
+                    }
+                }
+                Some(length) => {
+                    if self.state.text(&self.reader.get_ref().buffer()[..length]) {
+                        self.consumed = length;
+                        let text = &self.reader.get_ref().buffer()[..length];
+                        return Ok(Some(ReplyEvent::Event(text_event(text)?)));
+                    }
+                    self.reader.get_mut().consume(length);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{duplex, DuplexStream};
+
+    use super::*;
+
+    const HELLO: &str = r#"<hello xmlns="urn:ietf:params:xml:ns:netconf:base:1.0"><capabilities><capability>urn:ietf:params:netconf:base:1.0</capability></capabilities></hello>]]>]]>"#;
+
+    /// Answers each RPC with its message-id as output, until the client is dropped.
+    async fn router(mut stream: DuplexStream) {
+        stream.write_all(HELLO.as_bytes()).await.unwrap();
+        let mut framer = Framer::new();
+        let mut buffer = [0u8; 4096];
+        loop {
+            while let Some(message) = framer.next_message() {
+                let xml = String::from_utf8(message).unwrap();
+                let Some(start) = xml.find(r#"message-id=""#) else {
+                    continue;
+                };
+                let id = &xml[start + 12..];
+                let id = &id[..id.find('"').unwrap()];
+                let reply = format!(r#"<rpc-reply message-id="{id}"><output>reply &amp; {id}</output></rpc-reply>]]>]]>"#);
+                stream.write_all(reply.as_bytes()).await.unwrap();
+            }
+            match stream.read(&mut buffer).await {
+                Ok(0) | Err(_) => return,
+                Ok(bytes) => framer.push(&buffer[..bytes]),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_over_duplex() {
+        let (client, server) = duplex(64 * 1024);
+        let server = tokio::spawn(router(server));
+        let mut client = AsyncNETCONFClient::new(client);
+        client.init().await.unwrap();
+
+        let outcome = client.send_command("show version".into(), "text".into()).await.unwrap();
+        assert_eq!(outcome.value, "reply & 1");
+        let outcomes = client.send_commands(vec!["show a".into(), "show b".into()], "text".into()).await.unwrap();
+        let values: Vec<String> = outcomes.into_iter().map(|outcome| outcome.unwrap().value).collect();
+        assert_eq!(values, ["reply & 2", "reply & 3"]);
+
+        let mut stream = client.stream_command("show log".into(), "text".into()).await.unwrap();
+        let mut text = String::new();
+        while let Some(event) = stream.next_event().await.unwrap() {
+            if let ReplyEvent::Event(Event::Text(chunk)) = event {
+                text.push_str(&chunk.unescape().unwrap());
+            }
+        }
+        assert_eq!(text, "reply & 4");
+
+        drop(stream);
+        drop(client);
+        server.await.unwrap();
+    }
+}
@@ -0,0 +1,348 @@
+//! The methods shared by the blocking and the async client.
+//!
+//! Both clients are generated from this one definition, in the way quick-xml shares
+//! its readers. They only differ in being `async` and in how they `fill` the framer
+//! from the transport and `write` to it, which each provides itself. Names are
+//! resolved where the macro is invoked, so `Instant` is tokio's in the async client.
+
+/// Implements the RPCs for `$client`, whose replies are streamed with `$stream`.
+/// Passing `async, await` makes them `async`.
+macro_rules! impl_client {
+    ($client:ident, $stream:ident $(, $async:ident, $await:ident)?) => {
+        impl $client {
+            /// Sets how long to wait for a reply, `None` waits forever.
+            pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+                self.timeout = timeout;
+            }
+
+            /// Sets how long to wait for the reply to a commit, defaults to the regular timeout.
+            pub fn set_commit_timeout(&mut self, timeout: Option<Duration>) {
+                self.commit_timeout = timeout;
+            }
+
+            pub fn timeout(&self) -> Option<Duration> {
+                self.timeout
+            }
+
+            pub fn commit_timeout(&self) -> Option<Duration> {
+                self.commit_timeout
+            }
+
+            pub $($async)? fn init(&mut self) -> NETCONFResult<()> {
+                self.send_hello() $(.$await)? ?;
+                self.read_hello() $(.$await)? ?;
+
+                Ok(())
+            }
+
+            pub $($async)? fn read(&mut self) -> NETCONFResult<String> {
+                self.read_message(self.deadline()) $(.$await)?
+            }
+
+            $($async)? fn read_message(&mut self, deadline: Option<Instant>) -> NETCONFResult<String> {
+                self.skip_unfinished_message() $(.$await)? ?;
+                loop {
+                    if let Some(message) = self.framer.next_message() {
+                        return Ok(String::from_utf8(message)?);
+                    }
+                    self.fill(deadline) $(.$await)? ?;
+                }
+            }
+
+            fn deadline(&self) -> Option<Instant> {
+                self.timeout.map(|timeout| Instant::now() + timeout)
+            }
+
+            /// Drops the rest of a message whose reply stream was not read to the end.
+            $($async)? fn skip_unfinished_message(&mut self) -> NETCONFResult<()> {
+                while self.unfinished_message {
+                    let (_, end) = self.framer.take_partial();
+                    if end {
+                        self.unfinished_message = false;
+                    } else {
+                        self.fill(self.deadline()) $(.$await)? ?;
+                    }
+                }
+                Ok(())
+            }
+
+            $($async)? fn send_hello(&mut self) -> NETCONFResult<()> {
+                let payload = message::hello_payload()?;
+                message::log_sent(None, &payload);
+                self.write(payload.as_bytes()) $(.$await)? ?;
+                Ok(())
+            }
+
+            $($async)? fn read_hello(&mut self) -> NETCONFResult<Hello> {
+                let str = self.read() $(.$await)? ?;
+                message::log_received(None, &str, None);
+                let hello = from_str(&str)?;
+                Ok(hello)
+            }
+
+            /// Sends an RPC and returns its message-id.
+            $($async)? fn send_rpc(&mut self, body: &str) -> NETCONFResult<u64> {
+                self.message_id += 1;
+                let payload = message::rpc_payload(self.message_id, body);
+                message::log_sent(Some(self.message_id), &payload);
+                self.write(payload.as_bytes()) $(.$await)? ?;
+                Ok(self.message_id)
+            }
+
+            /// Reads the next `rpc-reply`, queueing notifications received in the meantime.
+            $($async)? fn read_rpc_reply(&mut self) -> NETCONFResult<String> {
+                loop {
+                    let xml = self.read() $(.$await)? ?;
+                    if !message::is_notification(&xml)? {
+                        return Ok(xml);
+                    }
+                    message::log_received(None, &xml, None);
+                    self.notifications.push_back(xml);
+                }
+            }
+
+            /// Reads the reply to `message_id`, turning `rpc-error`s into errors and collecting warnings.
+            $($async)? fn read_reply(&mut self, message_id: u64, sent: Instant) -> NETCONFResult<Reply> {
+                let mut xml = self.read_rpc_reply() $(.$await)? ?;
+                while message::is_stale_reply(&xml, message_id)? {
+                    xml = self.read_rpc_reply() $(.$await)? ?;
+                }
+                message::log_received(Some(message_id), &xml, Some(sent.elapsed()));
+                Reply::parse(xml)
+            }
+
+            $($async)? fn request<Req: Serialize>(&mut self, request: &Req) -> NETCONFResult<Reply> {
+                let sent = Instant::now();
+                let message_id = self.send_rpc(&message::request_body(request)?) $(.$await)? ?;
+                self.read_reply(message_id, sent) $(.$await)?
+            }
+
+            /// Sends all requests, reading replies in between once [`PIPELINE_DEPTH`] are
+            /// outstanding, and returns the replies in order.
+            $($async)? fn request_all<Req: Serialize>(&mut self, requests: &[Req]) -> NETCONFResult<Vec<NETCONFResult<Reply>>> {
+                self.skip_unfinished_message() $(.$await)? ?;
+                let mut pipeline = Pipeline::new(Instant::now().into());
+                for request in requests {
+                    if pipeline.open() >= PIPELINE_DEPTH {
+                        let xml = self.read_rpc_reply() $(.$await)? ?;
+                        pipeline.receive(xml)?;
+                    }
+                    pipeline.sent(self.send_rpc(&message::request_body(request)?) $(.$await)? ?);
+                }
+                while pipeline.open() > 0 {
+                    let xml = self.read_rpc_reply() $(.$await)? ?;
+                    pipeline.receive(xml)?;
+                }
+                Ok(pipeline.into_replies())
+            }
+
+            /// Sends `request` as RPC and deserializes the whole `rpc-reply` into `Resp`.
+            ///
+            /// `Req` is serialized as the single child of `<rpc>`, so it has to be named like
+            /// the RPC, e.g. `#[serde(rename = "get-route-information")]`. The fields of `Resp`
+            /// correspond to the child elements of `<rpc-reply>`. Replies containing an
+            /// `rpc-error` of severity error are returned as [`NETCONFError::RpcError`].
+            pub $($async)? fn call<Req: Serialize, Resp: DeserializeOwned>(&mut self, request: &Req) -> NETCONFResult<Outcome<Resp>> {
+                self.request(request) $(.$await)? ?.into_typed()
+            }
+
+            /// Like [`Self::call`], but sends all requests before waiting for the first reply.
+            ///
+            /// This saves a round trip per request on slow links. Each reply is returned on its
+            /// own, so an `rpc-error` only fails its request, while transport errors fail all.
+            pub $($async)? fn call_all<Req: Serialize, Resp: DeserializeOwned>(
+                &mut self,
+                requests: &[Req],
+            ) -> NETCONFResult<Vec<NETCONFResult<Outcome<Resp>>>> {
+                let replies = self.request_all(requests) $(.$await)? ?;
+                Ok(replies.into_iter().map(|reply| reply?.into_typed()).collect())
+            }
+
+            /// Sends `request` as RPC and expects an `<ok/>` reply.
+            pub $($async)? fn call_ok<Req: Serialize>(&mut self, request: &Req) -> NETCONFResult<Outcome<()>> {
+                self.request(request) $(.$await)? ?.into_ok()
+            }
+
+            /// Sends the given XML wrapped into an `<rpc>`, unless it is one already, and returns
+            /// the pretty printed reply.
+            pub $($async)? fn send_raw_rpc(&mut self, body: &str) -> NETCONFResult<Outcome<String>> {
+                let sent = Instant::now();
+                let body = xml::rpc_content(body)?;
+                let message_id = self.send_rpc(body.trim()) $(.$await)? ?;
+                self.read_reply(message_id, sent) $(.$await)? ?.into_raw_output()
+            }
+
+            pub $($async)? fn send_command(&mut self, command: String, format: String) -> NETCONFResult<Outcome<String>> {
+                let c = RPCCommand::Command {
+                    command,
+                    format: format.clone(),
+                };
+                self.request(&c) $(.$await)? ?.into_command_output(&format)
+            }
+
+            /// Runs several commands like [`Self::send_command`], sending them without waiting
+            /// for each reply.
+            pub $($async)? fn send_commands(&mut self, commands: Vec<String>, format: String) -> NETCONFResult<Vec<NETCONFResult<Outcome<String>>>> {
+                let requests: Vec<RPCCommand> = commands
+                    .into_iter()
+                    .map(|command| RPCCommand::Command {
+                        command,
+                        format: format.clone(),
+                    })
+                    .collect();
+                let replies = self.request_all(&requests) $(.$await)? ?;
+                Ok(replies
+                    .into_iter()
+                    .map(|reply| reply?.into_command_output(&format))
+                    .collect())
+            }
+
+            /// Sends `request` as RPC and returns the reply as a stream of events, without
+            /// buffering the whole reply.
+            pub $($async)? fn stream_rpc<Req: Serialize>(&mut self, request: &Req) -> NETCONFResult<$stream<'_>> {
+                self.skip_unfinished_message() $(.$await)? ?;
+                let sent = Instant::now();
+                let message_id = self.send_rpc(&message::request_body(request)?) $(.$await)? ?;
+                Ok($stream::new(self, message_id, sent))
+            }
+
+            /// Like [`Self::send_command`], but streams the reply.
+            pub $($async)? fn stream_command(&mut self, command: String, format: String) -> NETCONFResult<$stream<'_>> {
+                let c = RPCCommand::Command { command, format };
+                self.stream_rpc(&c) $(.$await)?
+            }
+
+            /// Subscribes to event notifications (RFC 5277), which are read with
+            /// [`Self::next_notification`].
+            ///
+            /// `stream` defaults to `NETCONF` and `filter` is a subtree filter in XML.
+            /// `start_time` replays the events since then, if the stream supports it.
+            pub $($async)? fn create_subscription(
+                &mut self,
+                stream: Option<&str>,
+                filter: Option<&str>,
+                start_time: Option<&str>,
+            ) -> NETCONFResult<Outcome<()>> {
+                let body = message::create_subscription(stream, filter, start_time);
+                let sent = Instant::now();
+                let message_id = self.send_rpc(&body) $(.$await)? ?;
+                self.read_reply(message_id, sent) $(.$await)? ?.into_ok()
+            }
+
+            /// Waits for the next notification, regardless of the timeout.
+            pub $($async)? fn next_notification(&mut self) -> NETCONFResult<Notification> {
+                let xml = match self.notifications.pop_front() {
+                    Some(xml) => xml,
+                    None => {
+                        let xml = self.read_message(None) $(.$await)? ?;
+                        message::log_received(None, &xml, None);
+                        xml
+                    }
+                };
+                let root = xml::root_element(&xml)?;
+                if root != "notification" {
+                    return Err(NETCONFError::UnexpectedMessage(root));
+                }
+                Ok(Notification::from_xml(xml)?)
+            }
+
+            pub $($async)? fn lock_configuration(&mut self) -> NETCONFResult<Outcome<()>> {
+                let c = RPCCommand::LockConfiguration {};
+                self.request(&c) $(.$await)? ?.into_empty()
+            }
+
+            pub $($async)? fn unlock_configuration(&mut self) -> NETCONFResult<Outcome<()>> {
+                let c = RPCCommand::UnlockConfiguration {};
+                self.request(&c) $(.$await)? ?.into_optional_ok()
+            }
+
+            /// Like [`Self::request`], but waits for the commit timeout.
+            $($async)? fn commit_request<Req: Serialize>(&mut self, request: &Req) -> NETCONFResult<Reply> {
+                let timeout = self.commit_timeout.or(self.timeout);
+                let default_timeout = std::mem::replace(&mut self.timeout, timeout);
+                let result = self.request(request) $(.$await)?;
+                self.timeout = default_timeout;
+                result
+            }
+
+            /// Like [`Self::call_ok`], but waits for the commit timeout and returns the commit
+            /// results.
+            $($async)? fn commit<Req: Serialize>(&mut self, request: &Req) -> NETCONFResult<Outcome<String>> {
+                self.commit_request(request) $(.$await)? ?.into_commit_results()
+            }
+
+            /// Commits the candidate, confirmed within `confirm_timeout` minutes if given.
+            ///
+            /// A confirmed commit with a `persist` token can be confirmed or cancelled from any
+            /// session by passing the token as persist-id.
+            ///
+            /// Returns the commit results, empty unless the commit is synchronized.
+            pub $($async)? fn apply_configuration(&mut self, confirm_timeout: Option<i32>, persist: Option<String>, options: &CommitOptions) -> NETCONFResult<Outcome<String>> {
+                let c = message::commit(confirm_timeout, persist, options);
+                self.commit(&c) $(.$await)?
+            }
+
+            /// Schedules a commit of the candidate for `at_time`, either `hh:mm[:ss]` or
+            /// `yyyy-mm-dd hh:mm[:ss]` in the router's time zone, and returns the commit results.
+            pub $($async)? fn schedule_commit(&mut self, at_time: String, options: &CommitOptions) -> NETCONFResult<Outcome<String>> {
+                let c = message::schedule_commit(at_time, options);
+                self.commit(&c) $(.$await)?
+            }
+
+            /// Confirms a confirmed commit, the one with the given persist token if any.
+            /// Without a token a `commit check` confirms it, leaving other changes of the
+            /// candidate uncommitted.
+            pub $($async)? fn confirm_configuration(&mut self, persist_id: Option<String>) -> NETCONFResult<Outcome<String>> {
+                let c = message::confirm(persist_id);
+                self.commit(&c) $(.$await)?
+            }
+
+            /// Cancels a confirmed commit, rolling back immediately. Without a persist token,
+            /// only a commit of this session can be cancelled.
+            pub $($async)? fn cancel_commit(&mut self, persist_id: Option<String>) -> NETCONFResult<Outcome<()>> {
+                let c = RPCCommand::CancelCommit { persist_id };
+                self.commit_request(&c) $(.$await)? ?.into_ok()
+            }
+
+            pub $($async)? fn discard_changes(&mut self) -> NETCONFResult<Outcome<()>> {
+                let c = RPCCommand::DiscardChanges {};
+                self.call_ok(&c) $(.$await)?
+            }
+
+            pub $($async)? fn load_configuration(&mut self, cfg: String, action: String, format: String) -> NETCONFResult<Outcome<()>> {
+                let c = message::load_configuration(cfg, action, format)?;
+                self.request(&c) $(.$await)? ?.into_load_configuration_results()
+            }
+
+            pub $($async)? fn rollback_configuration(&mut self, rollback: u32) -> NETCONFResult<Outcome<()>> {
+                let c = xml::RollbackConfiguration { rollback };
+                self.request(&c) $(.$await)? ?.into_load_configuration_results()
+            }
+
+            pub $($async)? fn get_configuration(&mut self, format: String) -> NETCONFResult<Outcome<String>> {
+                let c = RPCCommand::GetConfiguration {
+                    format,
+                    rollback: None,
+                    compare: None,
+                };
+                self.request(&c) $(.$await)? ?.into_configuration()
+            }
+
+            /// Returns the commit history and the commits scheduled with `commit at`.
+            pub $($async)? fn get_commit_information(&mut self) -> NETCONFResult<Outcome<CommitInformation>> {
+                self.request(&RPCCommand::GetCommitInformation {}) $(.$await)? ?.into_commit_information()
+            }
+
+            pub $($async)? fn diff_configuration(&mut self, format: String) -> NETCONFResult<Outcome<String>> {
+                let c = RPCCommand::GetConfiguration {
+                    format,
+                    rollback: Some("0".to_string()),
+                    compare: Some("rollback".to_string()),
+                };
+                self.request(&c) $(.$await)? ?.into_diff()
+            }
+        }
+    };
+}
+
+pub(super) use impl_client;
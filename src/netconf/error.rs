@@ -1,20 +1,43 @@
 use super::xml::RPCError;
-use crate::netconf::RPCReplyCommand;
+use crate::netconf::xml::RPCReplyCommand;
+use crate::ssh::error::SSHError;
 
 #[derive(Debug, thiserror::Error)]
 pub enum NETCONFError {
     #[error("{0}")]
     IoError(#[from] std::io::Error),
     #[error("{0}")]
+    SshError(#[from] SSHError),
+    #[error("{0}")]
     XmlError(#[from] quick_xml::Error),
     #[error("{0}")]
     XmlDeError(#[from] quick_xml::DeError),
+    #[error("No reply within {0:?}")]
+    Timeout(std::time::Duration),
+    #[error("Invalid UTF-8 in reply: {0}")]
+    InvalidUtf8(#[from] std::string::FromUtf8Error),
+    #[error("Connection closed")]
+    ConnectionClosed,
+    #[error("Reply to unknown message-id {0}")]
+    UnexpectedMessageId(String),
+    #[error("Unexpected {0} message")]
+    UnexpectedMessage(String),
+    #[error("Unsupported configuration format {0}")]
+    UnsupportedFormat(String),
     #[error("Missing OK")]
     MissingOk,
+    #[error("Loading the configuration failed with {count} errors{}", errors.iter().map(|error| format!("\n{}", error)).collect::<String>())]
+    LoadErrors { errors: Vec<RPCError>, count: u32 },
     #[error("Unexpected command: {0}")]
-    UnexpectedCommand(RPCReplyCommand),
+    UnexpectedCommand(Box<RPCReplyCommand>),
     #[error("{0}")]
-    RpcError(#[from] RPCError),
+    RpcError(#[from] Box<RPCError>),
+}
+
+impl From<RPCError> for NETCONFError {
+    fn from(err: RPCError) -> Self {
+        NETCONFError::RpcError(Box::new(err))
+    }
 }
 
 pub type NETCONFResult<T> = Result<T, NETCONFError>;
@@ -0,0 +1,144 @@
+use memmem::{Searcher, TwoWaySearcher};
+
+/// End of message marker of NETCONF 1.0.
+pub const DELIMITER: &[u8] = b"]]>]]>";
+
+/// Splits a byte stream into messages delimited by `]]>]]>`.
+///
+/// Received bytes are searched only once, bytes after a delimiter are kept
+/// for the following message. Taken messages are only skipped over, the
+/// buffer is compacted when more bytes arrive.
+pub struct Framer {
+    buffer: Vec<u8>,
+    /// Where the current message starts in `buffer`
+    start: usize,
+    /// How many bytes of the current message are known not to contain a delimiter
+    searched: usize,
+    searcher: TwoWaySearcher<'static>,
+}
+
+impl Default for Framer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Framer {
+    pub fn new() -> Framer {
+        Framer {
+            buffer: vec![],
+            start: 0,
+            searched: 0,
+            searcher: TwoWaySearcher::new(DELIMITER),
+        }
+    }
+
+    /// Appends bytes received from the transport.
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.drain(..self.start);
+        self.start = 0;
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Returns the end of the current message, if the delimiter was received.
+    fn find_delimiter(&self) -> Option<usize> {
+        // A delimiter may be split across two reads.
+        let from = self.start + self.searched.saturating_sub(DELIMITER.len() - 1);
+        self.searcher
+            .search_in(&self.buffer[from..])
+            .map(|pos| from + pos)
+    }
+
+    /// Takes the current message up to `end`, skipping the delimiter following it.
+    fn split_message(&mut self, end: usize) -> Vec<u8> {
+        let message = self.buffer[self.start..end].to_vec();
+        self.start = end + DELIMITER.len();
+        self.searched = 0;
+        message
+    }
+
+    /// Takes the next complete message out of the buffer.
+    pub fn next_message(&mut self) -> Option<Vec<u8>> {
+        match self.find_delimiter() {
+            Some(end) => Some(self.split_message(end)),
+            None => {
+                self.searched = self.buffered();
+                None
+            }
+        }
+    }
+
+    /// Takes the bytes of the current message received so far, without waiting for
+    /// its end. Returns whether the message is complete.
+    pub fn take_partial(&mut self) -> (Vec<u8>, bool) {
+        if let Some(end) = self.find_delimiter() {
+            return (self.split_message(end), true);
+        }
+        // Keep what could be the start of a delimiter.
+        let keep = self.buffered().min(DELIMITER.len() - 1);
+        let end = self.buffer.len() - keep;
+        let data = self.buffer[self.start..end].to_vec();
+        self.start = end;
+        self.searched = keep;
+        (data, false)
+    }
+
+    /// Number of buffered bytes not yet returned as a message.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len() - self.start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_in_one_read() {
+        let mut framer = Framer::new();
+        framer.push(b"<a/>]]>]]><b/>]]>]]><c");
+        assert_eq!(framer.next_message().unwrap(), b"<a/>");
+        assert_eq!(framer.next_message().unwrap(), b"<b/>");
+        assert_eq!(framer.next_message(), None);
+        assert_eq!(framer.buffered(), 2);
+
+        framer.push(b"/>]]>]]>");
+        assert_eq!(framer.next_message().unwrap(), b"<c/>");
+        assert_eq!(framer.buffered(), 0);
+    }
+
+    #[test]
+    fn delimiter_split_across_reads() {
+        for split in 1..DELIMITER.len() {
+            let mut framer = Framer::new();
+            framer.push(b"<a/>");
+            framer.push(&DELIMITER[..split]);
+            assert_eq!(framer.next_message(), None);
+            framer.push(&DELIMITER[split..]);
+            assert_eq!(framer.next_message().unwrap(), b"<a/>");
+        }
+    }
+
+    #[test]
+    fn partial_delimiter_in_message() {
+        let mut framer = Framer::new();
+        framer.push(b"<a>]]>]]");
+        assert_eq!(framer.next_message(), None);
+        framer.push(b"</a>]]>]");
+        assert_eq!(framer.next_message(), None);
+        framer.push(b"]>");
+        assert_eq!(framer.next_message().unwrap(), b"<a>]]>]]</a>");
+    }
+
+    #[test]
+    fn take_partial_keeps_possible_delimiter() {
+        let mut framer = Framer::new();
+        framer.push(b"<a>text]]>]");
+        assert_eq!(framer.take_partial(), (b"<a>tex".to_vec(), false));
+        framer.push(b"]x");
+        assert_eq!(framer.take_partial(), (b"t]".to_vec(), false));
+        framer.push(b"</a>]]>]]><b/>");
+        assert_eq!(framer.take_partial(), (b"]>]]x</a>".to_vec(), true));
+        assert_eq!(framer.buffered(), 4);
+    }
+}
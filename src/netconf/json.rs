@@ -0,0 +1,106 @@
+use quick_xml::{events::Event, Reader};
+use serde_json::{Map, Value};
+
+/// Converts the Junos JSON representation into idiomatic JSON.
+///
+/// Junos wraps every element into a list of objects and every value into `[{"data": ..}]`.
+/// The values are unwrapped and `attributes` are dropped. Lists of elements are kept,
+/// even with a single element, so the shape does not depend on the number of elements.
+pub fn flatten(value: Value) -> Value {
+    match value {
+        Value::Array(mut values) if values.len() == 1 && is_data(&values[0]) => flatten(values.remove(0)),
+        Value::Array(values) => Value::Array(values.into_iter().map(flatten).collect()),
+        Value::Object(mut object) => {
+            object.remove("attributes");
+            if object.len() == 1 && object.contains_key("data") {
+                return flatten(object.remove("data").unwrap_or(Value::Null));
+            }
+            Value::Object(
+                object
+                    .into_iter()
+                    .map(|(key, value)| (key, flatten(value)))
+                    .collect::<Map<String, Value>>(),
+            )
+        }
+        other => other,
+    }
+}
+
+/// Whether `value` is the `{"data": ..}` object Junos wraps values into.
+fn is_data(value: &Value) -> bool {
+    match value {
+        Value::Object(object) => object.contains_key("data") && object.keys().all(|key| key == "data" || key == "attributes"),
+        _ => false,
+    }
+}
+
+/// Inserts `value` under `key`, turning repeated keys into a list.
+fn insert(object: &mut Map<String, Value>, key: String, value: Value) {
+    match object.get_mut(&key) {
+        Some(Value::Array(values)) => values.push(value),
+        Some(existing) => {
+            let first = existing.take();
+            *existing = Value::Array(vec![first, value]);
+        }
+        None => {
+            object.insert(key, value);
+        }
+    }
+}
+
+/// Converts an XML element into JSON.
+///
+/// Child elements become keys of an object, repeated ones a list, and elements with
+/// only text their text. Attributes and namespaces are dropped. The result is an
+/// object with the root element as its only key.
+pub fn from_xml(xml: &str) -> Result<Value, quick_xml::Error> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+    // (name, children, text) of the open elements
+    let mut stack: Vec<(String, Map<String, Value>, String)> = vec![(String::new(), Map::new(), String::new())];
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                let name = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+                stack.push((name, Map::new(), String::new()));
+            }
+            Event::Empty(e) => {
+                let name = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+                if let Some((_, parent, _)) = stack.last_mut() {
+                    insert(parent, name, Value::Null);
+                }
+            }
+            Event::Text(text) => {
+                if let Some((_, _, content)) = stack.last_mut() {
+                    content.push_str(&text.unescape()?);
+                }
+            }
+            Event::CData(data) => {
+                if let Some((_, _, content)) = stack.last_mut() {
+                    content.push_str(&String::from_utf8_lossy(&data));
+                }
+            }
+            Event::End(_) if stack.len() > 1 => {
+                let Some((name, children, text)) = stack.pop() else {
+                    break;
+                };
+                let value = match (children.is_empty(), text.is_empty()) {
+                    (true, true) => Value::Null,
+                    (true, false) => Value::String(text),
+                    (false, true) => Value::Object(children),
+                    (false, false) => {
+                        let mut children = children;
+                        children.insert("$text".to_string(), Value::String(text));
+                        Value::Object(children)
+                    }
+                };
+                if let Some((_, parent, _)) = stack.last_mut() {
+                    insert(parent, name, value);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(stack.pop().map(|(_, root, _)| Value::Object(root)).unwrap_or(Value::Null))
+}
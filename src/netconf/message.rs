@@ -0,0 +1,457 @@
+//! Building requests and interpreting replies, independent of how they are transported.
+
+use quick_xml::{de::from_str, escape::escape, se::to_string};
+use serde::{de::DeserializeOwned, Serialize};
+
+use std::time::{Duration, Instant};
+
+use tracing::{debug, trace};
+
+use super::error::{NETCONFError, NETCONFResult};
+use super::xml::{self, LoadConfigurationResultsEnum, RPCError, RPCReply, RPCReplyCommand};
+use super::redact::redact;
+use super::{CommitOptions, Outcome};
+
+/// Target of the events carrying the full XML of each message.
+pub const WIRE_TARGET: &str = "rucli::wire";
+
+/// The RPCs of a batch sent ahead of their replies. Replies are read in between, so the
+/// router never stops reading because its replies are not read.
+pub(crate) const PIPELINE_DEPTH: usize = 8;
+
+/// Logs an outgoing message. The redacted XML is logged at trace level.
+pub(crate) fn log_sent(message_id: Option<u64>, payload: &str) {
+    debug!(direction = "sent", message_id, bytes = payload.len(), "netconf message");
+    let xml = payload.trim_end().trim_end_matches("]]>]]>").trim_end();
+    trace!(target: WIRE_TARGET, direction = "sent", message_id, "{}", redact(xml));
+}
+
+/// Logs an incoming message, `elapsed` being the time since its RPC was sent.
+pub(crate) fn log_received(message_id: Option<u64>, xml: &str, elapsed: Option<Duration>) {
+    log_streamed(message_id, xml.len(), Some(xml), elapsed);
+}
+
+/// Logs an incoming message of `bytes` bytes which was streamed, with its XML if it
+/// was kept because [`wire_traced`].
+pub(crate) fn log_streamed(message_id: Option<u64>, bytes: usize, xml: Option<&str>, elapsed: Option<Duration>) {
+    let elapsed_ms = elapsed.map(|elapsed| elapsed.as_millis() as u64);
+    debug!(direction = "received", message_id, bytes, elapsed_ms, "netconf message");
+    if let Some(xml) = xml {
+        trace!(target: WIRE_TARGET, direction = "received", message_id, "{}", redact(xml.trim()));
+    }
+}
+
+/// Whether the XML of messages is logged, so streamed replies have to be kept for it.
+pub(crate) fn wire_traced() -> bool {
+    tracing::enabled!(target: WIRE_TARGET, tracing::Level::TRACE)
+}
+
+/// Frames the client hello.
+pub(crate) fn hello_payload() -> NETCONFResult<String> {
+    let hello = xml::Hello {
+        capabilities: xml::Capabilities {
+            capability: vec!["urn:ietf:params:netconf:base:1.0".to_owned()],
+        },
+        namespace: None,
+        session_id: None,
+    };
+    let hello_xml = to_string(&hello)?;
+    Ok(format!("{}\n]]>]]>\n", hello_xml))
+}
+
+/// Serializes a request as the body of an RPC. Quotes in text are sent unescaped, as
+/// they always were, raw bodies are sent as given.
+pub(crate) fn request_body<Req: Serialize>(request: &Req) -> NETCONFResult<String> {
+    Ok(to_string(request)?.replace("&quot;", "\""))
+}
+
+/// Frames an RPC with `body`.
+pub(crate) fn rpc_payload(message_id: u64, body: &str) -> String {
+    format!("<rpc message-id=\"{}\">{}</rpc>\n]]>]]>\n", message_id, body)
+}
+
+/// Builds the body of a `create-subscription` RPC (RFC 5277).
+///
+/// `filter` is inserted as subtree filter as is, so it has to be valid XML.
+pub(crate) fn create_subscription(stream: Option<&str>, filter: Option<&str>, start_time: Option<&str>) -> String {
+    let mut body = String::from("<create-subscription xmlns=\"urn:ietf:params:xml:ns:netconf:notification:1.0\">");
+    if let Some(stream) = stream {
+        body.push_str(&format!("<stream>{}</stream>", escape(stream)));
+    }
+    if let Some(filter) = filter {
+        body.push_str(&format!("<filter type=\"subtree\">{}</filter>", filter.trim()));
+    }
+    if let Some(start_time) = start_time {
+        body.push_str(&format!("<startTime>{}</startTime>", escape(start_time)));
+    }
+    body.push_str("</create-subscription>");
+    body
+}
+
+/// Whether a message is a `notification` rather than an `rpc-reply`.
+pub(crate) fn is_notification(xml: &str) -> NETCONFResult<bool> {
+    Ok(xml::root_element(xml)? == "notification")
+}
+
+/// Whether `xml` is the late reply to an RPC sent before the one with `message_id`,
+/// e.g. one which timed out, and is to be dropped. Fails on replies to later RPCs.
+///
+/// Replies without a `message-id` are attributed to the RPC sent last.
+pub(crate) fn is_stale_reply(xml: &str, message_id: u64) -> NETCONFResult<bool> {
+    match RPCReply::message_id(xml)? {
+        Some(id) if id != message_id.to_string() => match id.parse::<u64>() {
+            Ok(earlier) if earlier < message_id => {
+                debug!("Dropping the late reply to message-id {}", id);
+                log_received(None, xml, None);
+                Ok(true)
+            }
+            _ => Err(NETCONFError::UnexpectedMessageId(id)),
+        },
+        _ => Ok(false),
+    }
+}
+
+/// Matches the replies to several RPCs sent at once to their requests.
+pub(crate) struct Pipeline {
+    message_ids: Vec<u64>,
+    sent: Instant,
+    replies: Vec<Option<NETCONFResult<Reply>>>,
+}
+
+impl Pipeline {
+    /// Starts a batch of RPCs sent starting at `sent`.
+    pub(crate) fn new(sent: Instant) -> Pipeline {
+        Pipeline {
+            message_ids: vec![],
+            sent,
+            replies: vec![],
+        }
+    }
+
+    /// Expects the reply to `message_id`.
+    pub(crate) fn sent(&mut self, message_id: u64) {
+        self.message_ids.push(message_id);
+        self.replies.push(None);
+    }
+
+    /// The number of RPCs still waiting for their reply.
+    pub(crate) fn open(&self) -> usize {
+        self.replies.iter().filter(|reply| reply.is_none()).count()
+    }
+
+    /// Assigns a reply by its `message-id`, or to the oldest open RPC if it has none.
+    ///
+    /// Replies to other RPCs, like one whose reply was not read to the end, are dropped.
+    pub(crate) fn receive(&mut self, xml: String) -> NETCONFResult<()> {
+        let open = |index: &usize| self.replies[*index].is_none();
+        let index = match RPCReply::message_id(&xml)? {
+            Some(id) => (0..self.message_ids.len())
+                .filter(open)
+                .find(|index| self.message_ids[*index].to_string() == id),
+            None => (0..self.message_ids.len()).find(open),
+        };
+        let Some(index) = index else {
+            debug!("Dropping a reply to an RPC outside of the batch");
+            log_received(None, &xml, None);
+            return Ok(());
+        };
+        log_received(Some(self.message_ids[index]), &xml, Some(self.sent.elapsed()));
+        self.replies[index] = Some(Reply::parse(xml));
+        Ok(())
+    }
+
+    /// Returns the replies in the order of the requests.
+    pub(crate) fn into_replies(self) -> Vec<NETCONFResult<Reply>> {
+        self.replies
+            .into_iter()
+            .map(|reply| reply.unwrap_or(Err(NETCONFError::MissingOk)))
+            .collect()
+    }
+}
+
+/// A reply without `rpc-error`s, holding the raw XML as well as the parsed elements.
+pub(crate) struct Reply {
+    xml: String,
+    commands: Vec<RPCReplyCommand>,
+    warnings: Vec<RPCError>,
+}
+
+impl Reply {
+    /// Parses a reply, turning `rpc-error`s into errors and collecting warnings.
+    pub(crate) fn parse(xml: String) -> NETCONFResult<Reply> {
+        let mut commands = vec![];
+        let mut warnings = vec![];
+        for result in RPCReply::from_xml(&xml)?.rpc_reply {
+            match result {
+                RPCReplyCommand::RPCError(error) => {
+                    if error.error_severity == "warning" {
+                        warnings.push(error);
+                    } else {
+                        return Err(error.into());
+                    }
+                }
+                other => commands.push(other),
+            }
+        }
+        Ok(Reply {
+            xml,
+            commands,
+            warnings,
+        })
+    }
+
+    fn outcome<T>(value: T, warnings: Vec<RPCError>) -> NETCONFResult<Outcome<T>> {
+        Ok(Outcome { value, warnings })
+    }
+
+    /// Deserializes the whole `rpc-reply`.
+    pub(crate) fn into_typed<Resp: DeserializeOwned>(self) -> NETCONFResult<Outcome<Resp>> {
+        Self::outcome(from_str(&self.xml)?, self.warnings)
+    }
+
+    /// Expects an `<ok/>`.
+    pub(crate) fn into_ok(self) -> NETCONFResult<Outcome<()>> {
+        let mut ok = None;
+        for result in self.commands {
+            match result {
+                RPCReplyCommand::Other(_) => {} // ???
+                RPCReplyCommand::Ok => ok = Some(()),
+                other => return Err(NETCONFError::UnexpectedCommand(Box::new(other))),
+            }
+        }
+        Self::outcome(ok.ok_or(NETCONFError::MissingOk)?, self.warnings)
+    }
+
+    /// Expects the result of a commit, which is an `<ok/>` or `commit-results`, and
+    /// returns the pretty printed `commit-results` if any.
+    pub(crate) fn into_commit_results(self) -> NETCONFResult<Outcome<String>> {
+        let mut results = vec![];
+        for result in self.commands {
+            match result {
+                RPCReplyCommand::Other(_) | RPCReplyCommand::Ok => {}
+                RPCReplyCommand::Xml(xml) if xml.starts_with("<commit-results") => {
+                    results.push(xml::pretty_print(&xml)?);
+                }
+                other => return Err(NETCONFError::UnexpectedCommand(Box::new(other))),
+            }
+        }
+        Self::outcome(results.join("\n"), self.warnings)
+    }
+
+    /// Expects a `commit-information`.
+    pub(crate) fn into_commit_information(self) -> NETCONFResult<Outcome<xml::CommitInformation>> {
+        let mut information = None;
+        for result in self.commands {
+            match result {
+                RPCReplyCommand::Xml(xml) if xml.starts_with("<commit-information") => {
+                    information = Some(from_str(&xml)?);
+                }
+                other => return Err(NETCONFError::UnexpectedCommand(Box::new(other))),
+            }
+        }
+        let information = information.ok_or_else(|| NETCONFError::UnexpectedMessage("empty".to_string()))?;
+        Self::outcome(information, self.warnings)
+    }
+
+    /// Expects an empty reply.
+    pub(crate) fn into_empty(self) -> NETCONFResult<Outcome<()>> {
+        if let Some(other) = self.commands.into_iter().next() {
+            return Err(NETCONFError::UnexpectedCommand(Box::new(other)));
+        }
+        Self::outcome((), self.warnings)
+    }
+
+    /// Expects an empty reply or an `<ok/>`.
+    pub(crate) fn into_optional_ok(self) -> NETCONFResult<Outcome<()>> {
+        for result in self.commands {
+            match result {
+                RPCReplyCommand::Ok => {} // sometimes sent, sometimes not
+                other => return Err(NETCONFError::UnexpectedCommand(Box::new(other))),
+            }
+        }
+        Self::outcome((), self.warnings)
+    }
+
+    /// Pretty prints all elements of the reply.
+    pub(crate) fn into_raw_output(self) -> NETCONFResult<Outcome<String>> {
+        let mut output = vec![];
+        for result in self.commands {
+            match result {
+                RPCReplyCommand::Ok => {}
+                RPCReplyCommand::Xml(xml) => output.push(xml::pretty_print(&xml)?),
+                other => output.push(other.to_string()),
+            }
+        }
+        Self::outcome(output.join("\n"), self.warnings)
+    }
+
+    /// Extracts the output of a `command` in the given format.
+    pub(crate) fn into_command_output(self, format: &str) -> NETCONFResult<Outcome<String>> {
+        let mut output = None;
+        for result in self.commands {
+            match result {
+                RPCReplyCommand::Other(text) if output.is_none() && format == "json" => {
+                    output = Some(text)
+                }
+                RPCReplyCommand::Output { text } if output.is_none() && format == "text" => {
+                    output = Some(text)
+                }
+                RPCReplyCommand::Xml(xml) if output.is_none() && format == "xml" => {
+                    output = Some(xml::pretty_print(&xml)?)
+                }
+                other => return Err(NETCONFError::UnexpectedCommand(Box::new(other))),
+            }
+        }
+        Self::outcome(output.ok_or(NETCONFError::MissingOk)?, self.warnings)
+    }
+
+    /// Expects `load-configuration-results` reporting success.
+    pub(crate) fn into_load_configuration_results(self) -> NETCONFResult<Outcome<()>> {
+        let mut warnings = self.warnings;
+        let mut load_config_result = None;
+        for result in self.commands {
+            match result {
+                RPCReplyCommand::LoadConfigurationResults(results) => {
+                    load_config_result = Some(results);
+                }
+                other => return Err(NETCONFError::UnexpectedCommand(Box::new(other))),
+            }
+        }
+        let mut ok = None;
+        let mut errors = vec![];
+        let mut count = 0;
+        for result in load_config_result
+            .ok_or(NETCONFError::MissingOk)?
+            .load_configuration_results
+        {
+            match result {
+                LoadConfigurationResultsEnum::RPCError(error) => {
+                    if error.error_severity == "warning" {
+                        warnings.push(error);
+                    } else {
+                        errors.push(error);
+                    }
+                }
+                LoadConfigurationResultsEnum::LoadErrorCount(load_error_count) => count = load_error_count.count(),
+                LoadConfigurationResultsEnum::Ok => ok = Some(()),
+            }
+        }
+        let count = count.max(errors.len() as u32);
+        if errors.len() == 1 && count == 1 {
+            return Err(errors.remove(0).into());
+        }
+        if count > 0 {
+            return Err(NETCONFError::LoadErrors { errors, count });
+        }
+        Self::outcome(ok.ok_or(NETCONFError::MissingOk)?, warnings)
+    }
+
+    /// Extracts the configuration of a `get-configuration`.
+    pub(crate) fn into_configuration(self) -> NETCONFResult<Outcome<String>> {
+        let mut config = None;
+        for result in self.commands {
+            match result {
+                RPCReplyCommand::ConfigurationText(data) | RPCReplyCommand::ConfigurationSet(data) => {
+                    config = Some(data.text);
+                }
+                other => return Err(NETCONFError::UnexpectedCommand(Box::new(other))),
+            }
+        }
+        Self::outcome(config.ok_or(NETCONFError::MissingOk)?, self.warnings)
+    }
+
+    /// Extracts the diff of a `get-configuration` comparing against a rollback.
+    pub(crate) fn into_diff(self) -> NETCONFResult<Outcome<String>> {
+        let mut diff_result = None;
+        for result in self.commands {
+            match result {
+                RPCReplyCommand::ConfigurationInformation {
+                    configuration_output,
+                } => {
+                    diff_result = Some(configuration_output);
+                }
+                other => return Err(NETCONFError::UnexpectedCommand(Box::new(other))),
+            }
+        }
+        Self::outcome(diff_result.ok_or(NETCONFError::MissingOk)?, self.warnings)
+    }
+}
+
+/// Builds a `commit-configuration`, confirmed within `confirm_timeout` minutes or
+/// scheduled for `at_time` if given.
+fn commit_configuration(confirm_timeout: Option<i32>, at_time: Option<String>, options: &CommitOptions) -> xml::RPCCommand {
+    let flag = |set: bool| set.then_some(xml::Flag {});
+    xml::RPCCommand::CommitConfiguration {
+        check: None,
+        log: options.comment.clone(),
+        synchronize: flag(options.synchronize || options.force_synchronize),
+        force_synchronize: flag(options.force_synchronize),
+        confirmed: confirm_timeout.map(|_| xml::ConfigurationConfirmed {}),
+        confirm_timeout,
+        at_time,
+    }
+}
+
+/// Builds the request committing the candidate, confirmed within `confirm_timeout`
+/// minutes if given.
+///
+/// With a `persist` token the confirmed commit is made with the RFC 6241 `commit`,
+/// so it can be confirmed or cancelled from another session. It takes no options.
+pub(crate) fn commit(confirm_timeout: Option<i32>, persist: Option<String>, options: &CommitOptions) -> xml::RPCCommand {
+    match (confirm_timeout, persist) {
+        (Some(confirm_timeout), Some(persist)) => xml::RPCCommand::Commit {
+            confirmed: Some(xml::ConfigurationConfirmed {}),
+            confirm_timeout: Some(confirm_timeout.max(1) as u32 * 60),
+            persist: Some(persist),
+            persist_id: None,
+        },
+        (confirm_timeout, _) => commit_configuration(confirm_timeout, None, options),
+    }
+}
+
+/// Builds the request scheduling a commit for `at_time`.
+pub(crate) fn schedule_commit(at_time: String, options: &CommitOptions) -> xml::RPCCommand {
+    commit_configuration(None, Some(at_time), options)
+}
+
+/// Builds the request confirming a confirmed commit, the one made with `persist_id`
+/// if given.
+///
+/// Without `persist_id` this is a `commit check`, which confirms a pending confirmed
+/// commit on Junos without committing other changes of the candidate.
+pub(crate) fn confirm(persist_id: Option<String>) -> xml::RPCCommand {
+    match persist_id {
+        Some(persist_id) => xml::RPCCommand::Commit {
+            confirmed: None,
+            confirm_timeout: None,
+            persist: None,
+            persist_id: Some(persist_id),
+        },
+        None => xml::RPCCommand::CommitConfiguration {
+            check: Some(xml::Flag {}),
+            log: None,
+            synchronize: None,
+            force_synchronize: None,
+            confirmed: None,
+            confirm_timeout: None,
+            at_time: None,
+        },
+    }
+}
+
+/// Builds the `load-configuration` request for the given format.
+pub(crate) fn load_configuration(cfg: String, action: String, format: String) -> NETCONFResult<xml::RPCCommand> {
+    let mut cfg_text = None;
+    let mut cfg_set = None;
+    match format.as_str() {
+        "text" => cfg_text = Some(cfg),
+        "set" => cfg_set = Some(cfg),
+        _ => return Err(NETCONFError::UnsupportedFormat(format)),
+    }
+    Ok(xml::RPCCommand::LoadConfiguration {
+        format,
+        action,
+        cfg_text,
+        cfg_set,
+    })
+}
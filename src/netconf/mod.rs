@@ -1,275 +1,225 @@
-use memmem::{Searcher, TwoWaySearcher};
-use std::io::{self, Read, Write};
-
-use quick_xml::{de::from_str, se::to_string};
-
-mod error;
+use std::collections::VecDeque;
+use std::io;
+use std::time::{Duration, Instant};
+
+use quick_xml::de::from_str;
+use serde::{de::DeserializeOwned, Serialize};
+
+#[cfg(feature = "async")]
+pub mod async_client;
+mod client;
+pub mod error;
+pub mod framer;
+pub mod json;
+mod message;
+pub mod record;
+pub mod redact;
+mod stream;
+pub mod transport;
 pub mod xml;
 
 use crate::netconf::error::NETCONFError;
-use crate::netconf::xml::LoadConfigurationResultsEnum;
-use crate::netconf::xml::RPCReplyCommand;
-use crate::netconf::xml::RPC;
+use crate::netconf::framer::Framer;
+use crate::netconf::transport::Transport;
+use crate::netconf::message::{Pipeline, Reply, PIPELINE_DEPTH};
+use crate::netconf::xml::RPCError;
+
+pub use self::message::WIRE_TARGET;
+pub use self::stream::{ReplyEvent, ReplyStream};
 
 use self::{
     error::NETCONFResult,
-    xml::{ConfigurationConfirmed, Hello, RPCCommand, RPCReply},
+    xml::{CommitInformation, Hello, Notification, RPCCommand},
 };
 
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
 pub struct NETCONFClient {
-    // FIXME: Technically, this could be generic.
-    channel: ssh2::Channel,
+    transport: Box<dyn Transport>,
+    framer: Framer,
+    read_buffer: Vec<u8>,
+    /// Set if a [`ReplyStream`] was dropped before the end of its message
+    unfinished_message: bool,
+    message_id: u64,
+    /// Notifications received while waiting for an `rpc-reply`
+    notifications: VecDeque<String>,
+    timeout: Option<Duration>,
+    commit_timeout: Option<Duration>,
 }
 
-impl NETCONFClient {
-    pub fn new(channel: ssh2::Channel) -> NETCONFClient {
-        return NETCONFClient { channel };
+/// The result of an RPC together with the warnings the router replied with.
+#[derive(Debug)]
+pub struct Outcome<T> {
+    pub value: T,
+    pub warnings: Vec<RPCError>,
+}
+
+impl<T> Outcome<T> {
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Outcome<U> {
+        Outcome {
+            value: f(self.value),
+            warnings: self.warnings,
+        }
     }
+}
 
-    pub fn init(&mut self) -> NETCONFResult<()> {
-        self.send_hello()?;
-        self.read_hello()?;
+/// Options of a commit made with `commit-configuration`.
+#[derive(Clone, Debug, Default)]
+pub struct CommitOptions {
+    /// Logged with the commit and shown by `show system commit`
+    pub comment: Option<String>,
+    /// Commit on both routing engines of a dual-RE chassis
+    pub synchronize: bool,
+    /// Synchronize even if the other routing engine has uncommitted changes or holds
+    /// a lock
+    pub force_synchronize: bool,
+}
 
-        return Ok(());
+impl NETCONFClient {
+    pub fn new(transport: impl Transport + 'static) -> NETCONFClient {
+        NETCONFClient {
+            transport: Box::new(transport),
+            framer: Framer::new(),
+            read_buffer: vec![0u8; READ_BUFFER_SIZE],
+            unfinished_message: false,
+            message_id: 0,
+            notifications: VecDeque::new(),
+            timeout: None,
+            commit_timeout: None,
+        }
     }
 
-    pub fn read(&mut self) -> io::Result<String> {
-        let mut read_buffer: Vec<u8> = vec![];
+    /// Reads the next bytes from the transport into the framer.
+    fn fill(&mut self, deadline: Option<Instant>) -> NETCONFResult<()> {
+        loop {
+            let mut read_timeout = None;
+            if let (Some(deadline), Some(timeout)) = (deadline, self.timeout) {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(NETCONFError::Timeout(timeout));
+                }
+                read_timeout = Some(remaining);
+            }
+            // Wake up in time for the next keepalive while waiting for a slow reply.
+            if let Some(keepalive) = self.transport.keepalive()? {
+                read_timeout = Some(read_timeout.map_or(keepalive, |t: Duration| t.min(keepalive)));
+            }
+            self.transport.set_read_timeout(read_timeout)?;
 
-        let mut buffer = [0u8; 128];
-        let search = TwoWaySearcher::new("]]>]]>".as_bytes());
-        while search.search_in(&read_buffer).is_none() {
-            let bytes = self.channel.read(&mut buffer)?;
-            read_buffer.extend(&buffer[..bytes]);
+            match self.transport.read(&mut self.read_buffer) {
+                Ok(0) => return Err(NETCONFError::ConnectionClosed),
+                Ok(bytes) => {
+                    self.framer.push(&self.read_buffer[..bytes]);
+                    return Ok(());
+                }
+                Err(e) if matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock) => continue,
+                Err(e) => return Err(e.into()),
+            }
         }
-        let pos = search.search_in(&read_buffer).unwrap();
-        let resp = String::from_utf8(read_buffer[..pos].to_vec()).unwrap();
-        // 6: ]]>]]>
-        read_buffer.drain(0..(pos + 6));
-        Ok(resp)
     }
 
     fn write(&mut self, payload: &[u8]) -> io::Result<()> {
-        self.channel.write_all(payload)
+        self.transport.write_all(payload)
     }
+}
 
-    fn send_hello(&mut self) -> NETCONFResult<()> {
-        let hello = xml::Hello {
-            capabilities: xml::Capabilities {
-                capability: vec!["urn:ietf:params:netconf:base:1.0".to_owned()],
-            },
-            namespace: None,
-            session_id: None,
-        };
-        let hello_xml = to_string(&hello)?;
-        let payload_mod = format!("{}\n]]>]]>\n", hello_xml);
-        //println!("{}", payload_mod);
-        let wb = self.write(payload_mod.as_bytes())?;
-        return Ok(wb);
-    }
+client::impl_client!(NETCONFClient, ReplyStream);
 
-    fn read_hello(&mut self) -> NETCONFResult<Hello> {
-        let str = self.read()?;
-        //eprintln!("{}", str);
-        let hello = from_str(&str)?;
-        return Ok(hello);
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    fn send_rpc(&mut self, rpc: RPC) -> NETCONFResult<()> {
-        let rpc_xml = to_string(&rpc)?;
-        let payload = format!("{}\n]]>]]>\n", rpc_xml).replace("&quot;", "\"");
-        //println!("{}", payload);
-        let wb = self.write(payload.as_bytes())?;
-        return Ok(wb);
-    }
+    const HELLO: &str = r#"<hello xmlns="urn:ietf:params:xml:ns:netconf:base:1.0"><capabilities><capability>urn:ietf:params:netconf:base:1.0</capability></capabilities></hello>"#;
 
-    fn read_result(&mut self) -> NETCONFResult<impl Iterator<Item = RPCReplyCommand>> {
-        let str = self.read()?;
-        //eprintln!("{}", str);
-        Ok(from_str::<RPCReply>(&str)?.rpc_reply.into_iter())
+    /// Replies with each message once the given number of messages was written.
+    struct MockTransport {
+        written: Vec<u8>,
+        replies: VecDeque<(usize, String)>,
     }
 
-    pub fn send_command(&mut self, command: String, format: String) -> NETCONFResult<String> {
-        let c = RPC {
-            rpc: RPCCommand::Command {
-                command,
-                format: format.clone(),
-            },
-        };
-        let _ = self.send_rpc(c)?;
-        let mut output = None;
-        for result in self.read_result()? {
-            match result {
-                RPCReplyCommand::RPCError(error) => {
-                    if error.error_severity == "warning" {
-                        eprintln!("{}", error);
-                    } else {
-                        return Err(error.into());
-                    }
-                }
-                RPCReplyCommand::Other(text) if output.is_none() && format == "json" => {
-                    output = Some(text)
-                }
-                RPCReplyCommand::Output { text } if output.is_none() && format == "text" => {
-                    output = Some(text)
-                }
-                other => return Err(NETCONFError::UnexpectedCommand(other)),
+    impl MockTransport {
+        fn new(replies: &[(usize, &str)]) -> MockTransport {
+            MockTransport {
+                written: vec![],
+                replies: replies.iter().map(|(after, xml)| (*after, format!("{}]]>]]>", xml))).collect(),
             }
         }
-        output.ok_or(NETCONFError::MissingOk)
     }
 
-    pub fn lock_configuration(&mut self) -> NETCONFResult<()> {
-        let c = RPC {
-            rpc: RPCCommand::LockConfiguration {},
-        };
-        let _ = self.send_rpc(c)?;
-        for result in self.read_result()? {
-            match result {
-                other => return Err(NETCONFError::UnexpectedCommand(other)),
+    impl io::Read for MockTransport {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let written = self.written.windows(framer::DELIMITER.len()).filter(|w| *w == framer::DELIMITER).count();
+            match self.replies.front() {
+                Some((after, _)) if *after <= written => {
+                    let (_, reply) = self.replies.pop_front().unwrap();
+                    buf[..reply.len()].copy_from_slice(reply.as_bytes());
+                    Ok(reply.len())
+                }
+                Some(_) => Err(io::ErrorKind::TimedOut.into()),
+                None => Ok(0),
             }
         }
-        Ok(())
     }
 
-    pub fn unlock_configuration(&mut self) -> NETCONFResult<()> {
-        let c = RPC {
-            rpc: RPCCommand::UnlockConfiguration {},
-        };
-        let _ = self.send_rpc(c)?;
-        for result in self.read_result()? {
-            match result {
-                RPCReplyCommand::Ok => {} // sometimes sent, sometimes not
-                other => return Err(NETCONFError::UnexpectedCommand(other)),
-            }
+    impl io::Write for MockTransport {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
         }
-        Ok(())
-    }
 
-    pub fn apply_configuration(&mut self, confirm_timeout: Option<i32>) -> NETCONFResult<()> {
-        if let Some(confirm_timeout) = confirm_timeout {
-            let c = RPC {
-                rpc: RPCCommand::CommitConfirmedConfiguration {
-                    confirm_timeout,
-                    confirmed: ConfigurationConfirmed {},
-                },
-            };
-            let _ = self.send_rpc(c)?;
-        } else {
-            let c = RPC {
-                rpc: RPCCommand::CommitConfiguration {},
-            };
-            let _ = self.send_rpc(c)?;
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
         }
-        let mut ok = None;
-        for result in self.read_result()? {
-            match result {
-                RPCReplyCommand::RPCError(error) => {
-                    if error.error_severity == "warning" {
-                        eprintln!("{}", error);
-                    } else {
-                        return Err(error.into());
-                    }
-                }
-                RPCReplyCommand::Other(_) => {} // ???
-                RPCReplyCommand::Ok => ok = Some(()),
-                other => return Err(NETCONFError::UnexpectedCommand(other)),
-            }
-        }
-        ok.ok_or(NETCONFError::MissingOk)
     }
 
-    pub fn confirm_configuration(&mut self) -> NETCONFResult<()> {
-        let c = RPC {
-            rpc: RPCCommand::CommitConfiguration {},
-        };
-        let _ = self.send_rpc(c)?;
-        let mut ok = None;
-        for result in self.read_result()? {
-            match result {
-                RPCReplyCommand::RPCError(error) => {
-                    if error.error_severity == "warning" {
-                        eprintln!("{}", error);
-                    } else {
-                        return Err(error.into());
-                    }
-                }
-                RPCReplyCommand::Ok => ok = Some(()),
-                other => return Err(NETCONFError::UnexpectedCommand(other)),
-            }
+    impl Transport for MockTransport {
+        fn set_read_timeout(&mut self, _: Option<Duration>) -> io::Result<()> {
+            Ok(())
         }
-        ok.ok_or(NETCONFError::MissingOk)
     }
 
-    pub fn load_configuration(&mut self, cfg: String, action: String, format: String) -> NETCONFResult<()> {
-        let mut cfg_text = None;
-        let mut cfg_set = None;
-        match format.as_str() {
-            "text" => cfg_text = Some(cfg),
-            "set" => cfg_set = Some(cfg),
-            _ => unimplemented!(),
-        }
-        let c = RPC {
-            rpc: RPCCommand::LoadConfiguration {
-                format,
-                action,
-                cfg_text,
-                cfg_set,
-            },
-        };
-        let _ = self.send_rpc(c)?;
+    fn output(message_id: u64, text: &str) -> String {
+        format!(r#"<rpc-reply message-id="{}"><output>{}</output></rpc-reply>"#, message_id, text)
+    }
 
-        let mut load_config_result = None;
-        for result in self.read_result()? {
-            match result {
-                RPCReplyCommand::LoadConfigurationResults(results) => {
-                    load_config_result = Some(results);
-                }
-                other => return Err(NETCONFError::UnexpectedCommand(other)),
-            }
-        }
-        let mut ok = None;
-        for result in load_config_result
-            .ok_or(NETCONFError::MissingOk)?
-            .load_configuration_results
-        {
-            match result {
-                LoadConfigurationResultsEnum::RPCError(error) => {
-                    if error.error_severity == "warning" {
-                        eprintln!("{}", error);
-                    } else {
-                        return Err(error.into());
-                    }
-                }
-                LoadConfigurationResultsEnum::LoadErrorCount(l) => {
-                    eprintln!("{:?}", l);
-                }
-                LoadConfigurationResultsEnum::Ok => ok = Some(()),
-            }
-        }
-        ok.ok_or(NETCONFError::MissingOk)
+    #[test]
+    fn late_reply_is_dropped_after_timeout() {
+        let (first, second, third) = (output(1, "one"), output(2, "two"), output(3, "three"));
+        let transport = MockTransport::new(&[(0, HELLO), (3, &first), (3, &second), (4, &third)]);
+        let mut client = NETCONFClient::new(transport);
+        client.init().unwrap();
+        client.set_timeout(Some(Duration::from_millis(20)));
+
+        let timed_out = client.send_command("show one".into(), "text".into());
+        assert!(matches!(timed_out, Err(NETCONFError::Timeout(_))));
+        assert_eq!(client.send_command("show two".into(), "text".into()).unwrap().value, "two");
+        assert_eq!(client.send_command("show three".into(), "text".into()).unwrap().value, "three");
     }
 
-    pub fn diff_configuration(&mut self, format: String) -> NETCONFResult<String> {
-        let c = RPC {
-            rpc: RPCCommand::GetConfiguration {
-                format: format,
-                rollback: Some("0".to_string()),
-                compare: Some("rollback".to_string()),
-            },
-        };
-        let _ = self.send_rpc(c)?;
-        let mut diff_result = None;
-        for result in self.read_result()? {
-            match result {
-                RPCReplyCommand::ConfigurationInformation {
-                    configuration_output,
-                } => {
-                    diff_result = Some(configuration_output);
-                }
-                other => return Err(NETCONFError::UnexpectedCommand(other)),
+    #[test]
+    fn text_is_streamed_in_pieces() {
+        let text = "x&amp;é€".repeat(3000);
+        let transport = MockTransport::new(&[(0, HELLO), (1, &output(1, &text))]);
+        let mut client = NETCONFClient::new(transport);
+        client.init().unwrap();
+        let mut stream = client.stream_command("show log".into(), "text".into()).unwrap();
+        let (mut pieces, mut streamed) = (0, String::new());
+        while let Some(event) = stream.next_event().unwrap() {
+            if let ReplyEvent::Event(quick_xml::events::Event::Text(text)) = event {
+                pieces += 1;
+                streamed.push_str(&text.unescape().unwrap());
             }
         }
-        diff_result.ok_or(NETCONFError::MissingOk)
+        assert!(pieces > 1);
+        assert_eq!(streamed, "x&é€".repeat(3000));
+    }
+
+    #[test]
+    fn reply_to_later_rpc_fails() {
+        let transport = MockTransport::new(&[(0, HELLO), (2, &output(2, "two"))]);
+        let mut client = NETCONFClient::new(transport);
+        client.init().unwrap();
+        let result = client.send_command("show one".into(), "text".into());
+        assert!(matches!(result, Err(NETCONFError::UnexpectedMessageId(id)) if id == "2"));
     }
 }
@@ -0,0 +1,269 @@
+//! Recording NETCONF sessions as JSON lines and replaying them without a router.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Read, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::framer::{Framer, DELIMITER};
+use super::redact::redact;
+use super::transport::Transport;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// A line of a recorded session.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RecordedMessage {
+    /// Milliseconds since the UNIX epoch
+    pub timestamp_ms: u64,
+    pub direction: Direction,
+    /// The message without the delimiter, with secrets redacted if the recorder was
+    /// [`Recorder::redacted`]
+    pub xml: String,
+}
+
+/// Wraps a transport and writes every complete message passing through it to `out`,
+/// as it was sent or received.
+pub struct Recorder<T: Transport, W: Write> {
+    inner: T,
+    out: W,
+    sent: Framer,
+    received: Framer,
+    redact: bool,
+}
+
+impl<T: Transport, W: Write> Recorder<T, W> {
+    pub fn new(inner: T, out: W) -> Recorder<T, W> {
+        Recorder {
+            inner,
+            out,
+            sent: Framer::new(),
+            received: Framer::new(),
+            redact: false,
+        }
+    }
+
+    /// Removes secrets from the recorded messages, so the recording can be shared.
+    pub fn redacted(mut self) -> Recorder<T, W> {
+        self.redact = true;
+        self
+    }
+
+    fn record(out: &mut W, framer: &mut Framer, direction: Direction, redacted: bool) -> io::Result<()> {
+        while let Some(message) = framer.next_message() {
+            let timestamp_ms = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64;
+            let xml = String::from_utf8_lossy(&message).trim().to_string();
+            let message = RecordedMessage {
+                timestamp_ms,
+                direction,
+                xml: if redacted { redact(&xml) } else { xml },
+            };
+            serde_json::to_writer(&mut *out, &message)?;
+            out.write_all(b"\n")?;
+            // Keep the transcript up to date in case the session hangs.
+            out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Transport, W: Write> Read for Recorder<T, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes = self.inner.read(buf)?;
+        self.received.push(&buf[..bytes]);
+        Self::record(&mut self.out, &mut self.received, Direction::Received, self.redact)?;
+        Ok(bytes)
+    }
+}
+
+impl<T: Transport, W: Write> Write for Recorder<T, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let bytes = self.inner.write(buf)?;
+        self.sent.push(&buf[..bytes]);
+        Self::record(&mut self.out, &mut self.sent, Direction::Sent, self.redact)?;
+        Ok(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: Transport, W: Write> Transport for Recorder<T, W> {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+
+    fn keepalive(&mut self) -> io::Result<Option<Duration>> {
+        self.inner.keepalive()
+    }
+}
+
+/// A transport playing back the received messages of a recorded session.
+///
+/// The messages the client sends are checked against the sent messages of the
+/// recording, a recording without any, like one written by hand, accepts any
+/// requests. After the last message the connection is closed.
+pub struct Replay {
+    messages: VecDeque<Vec<u8>>,
+    pending: Vec<u8>,
+    position: usize,
+    /// The recorded requests not sent yet
+    expected: VecDeque<String>,
+    verify: bool,
+    sent: Framer,
+}
+
+impl Replay {
+    /// Reads a session recorded by [`Recorder`].
+    pub fn from_reader(reader: impl BufRead) -> io::Result<Replay> {
+        let mut messages = VecDeque::new();
+        let mut expected = VecDeque::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let message: RecordedMessage = serde_json::from_str(&line)?;
+            match message.direction {
+                Direction::Received => {
+                    let mut bytes = message.xml.into_bytes();
+                    bytes.extend_from_slice(DELIMITER);
+                    messages.push_back(bytes);
+                }
+                Direction::Sent => expected.push_back(message.xml),
+            }
+        }
+        Ok(Replay {
+            messages,
+            pending: vec![],
+            position: 0,
+            verify: !expected.is_empty(),
+            expected,
+            sent: Framer::new(),
+        })
+    }
+
+    /// Fails unless `message` is the next request of the recording, possibly redacted.
+    fn verify(&mut self, message: &str) -> io::Result<()> {
+        if !self.verify {
+            return Ok(());
+        }
+        let mismatch = |expected: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("request differs from the recording, expected {} but sent {}", expected, message),
+            )
+        };
+        match self.expected.pop_front() {
+            Some(expected) if expected == message || expected == redact(message) => Ok(()),
+            Some(expected) => Err(mismatch(&expected)),
+            None => Err(mismatch("nothing")),
+        }
+    }
+}
+
+impl Read for Replay {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.pending.len() {
+            match self.messages.pop_front() {
+                Some(message) => {
+                    self.pending = message;
+                    self.position = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let bytes = buf.len().min(self.pending.len() - self.position);
+        buf[..bytes].copy_from_slice(&self.pending[self.position..self.position + bytes]);
+        self.position += bytes;
+        Ok(bytes)
+    }
+}
+
+impl Write for Replay {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.sent.push(buf);
+        while let Some(message) = self.sent.next_message() {
+            self.verify(String::from_utf8_lossy(&message).trim())?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Replay {
+    fn set_read_timeout(&mut self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::netconf::error::NETCONFError;
+    use crate::netconf::{CommitOptions, NETCONFClient};
+
+    /// A session recorded with [`Recorder`]: show version, a change of the host name
+    /// with a warning, its commit and the commit history.
+    const SESSION: &str = include_str!("testdata/session.jsonl");
+
+    fn client() -> NETCONFClient {
+        let mut client = NETCONFClient::new(Replay::from_reader(SESSION.as_bytes()).unwrap());
+        client.init().unwrap();
+        client
+    }
+
+    #[test]
+    fn replays_recorded_session() {
+        let mut client = client();
+        let version = client.send_command("show version".into(), "xml".into()).unwrap();
+        assert!(version.value.contains("<junos-version>23.4R1.9</junos-version>"));
+        let configuration = client.get_configuration("text".into()).unwrap();
+        assert_eq!(configuration.value, "system {\n    host-name r1;\n}");
+
+        client.lock_configuration().unwrap();
+        let load = client.load_configuration("system { host-name r2; }".into(), "replace".into(), "text".into());
+        let warnings = load.unwrap().warnings;
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].error_message, "statement not found");
+        let diff = client.diff_configuration("text".into()).unwrap();
+        assert!(diff.value.contains("+  host-name r2;"));
+        let options = CommitOptions {
+            comment: Some("rename".into()),
+            ..Default::default()
+        };
+        let results = client.apply_configuration(None, None, &options).unwrap();
+        assert!(results.value.contains("<commit-success/>"));
+
+        let information = client.get_commit_information().unwrap().value;
+        assert!(information.pending.is_empty());
+        assert_eq!(information.history.len(), 2);
+        assert_eq!(information.history[0].comment.as_deref(), Some("rename"));
+        assert_eq!(information.history[1].client.as_deref(), Some("cli"));
+        client.unlock_configuration().unwrap();
+    }
+
+    #[test]
+    fn request_differing_from_recording_fails() {
+        let mut client = client();
+        let result = client.send_command("show interfaces".into(), "xml".into());
+        let Err(NETCONFError::IoError(error)) = result else {
+            panic!("request was not verified: {:?}", result);
+        };
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("show version"));
+    }
+}
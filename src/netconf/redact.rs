@@ -0,0 +1,338 @@
+//! Removes secrets from NETCONF messages before they are logged.
+
+use std::ops::Range;
+
+/// Configuration statements whose value is a secret.
+const SECRETS: &[&str] = &[
+    "encrypted-password",
+    "plain-text-password-value",
+    "simple-password",
+    "secret",
+    "authentication-key",
+    "ascii-text",
+    "hexadecimal",
+    "pre-shared-key",
+    "password",
+    "authentication-password",
+    "privacy-password",
+    "key",
+];
+
+/// Secrets whose name is also that of a container, e.g. `key 0 { secret ..; }` of a
+/// key chain. Their value is only redacted if it ends the statement.
+const LEAF_SECRETS: &[&str] = &["key"];
+
+/// Junos appends this to the text configuration statements holding a secret.
+const SECRET_DATA: &str = "## SECRET-DATA";
+
+/// Statements which are secrets within the `snmp` hierarchy only, unlike the BGP
+/// communities of routes and policies.
+const SNMP_SECRETS: &[&str] = &["community"];
+
+const REDACTED: &str = "********";
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+/// Returns the length of the value starting at `value`, which is quoted or ends at
+/// whitespace, `;` or the next tag.
+fn value_length(value: &str) -> usize {
+    for quote in ["\"", "&quot;"] {
+        if let Some(rest) = value.strip_prefix(quote) {
+            let mut escaped = false;
+            for (i, c) in rest.char_indices() {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    _ if rest[i..].starts_with(quote) => return quote.len() + i + quote.len(),
+                    _ => {}
+                }
+            }
+            return value.len();
+        }
+    }
+    value
+        .find(|c: char| c.is_whitespace() || c == ';' || c == '<')
+        .unwrap_or(value.len())
+}
+
+/// Returns whether `value` starts with one of the `secrets`, like `ascii-text` in
+/// `pre-shared-key ascii-text ..`.
+fn starts_with_secret(value: &str, secrets: &[&str]) -> bool {
+    secrets
+        .iter()
+        .any(|secret| value.strip_prefix(secret).is_some_and(|after| !after.starts_with(is_word_char)))
+}
+
+/// Returns whether `rest`, following a value, ends the statement.
+fn ends_statement(rest: &str) -> bool {
+    let rest = rest.trim_start_matches([' ', '\t']);
+    rest.is_empty() || rest.starts_with([';', '\r', '\n', '<'])
+}
+
+/// Replaces the last value of the text configuration statements marked with
+/// `## SECRET-DATA`, whatever their name.
+fn redact_secret_data(text: &str) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let statement = line.find(SECRET_DATA).and_then(|marker| line[..marker].rfind(';'));
+        let Some(end) = statement else {
+            redacted.push_str(line);
+            continue;
+        };
+        let mut values = vec![];
+        let mut position = 0;
+        while let Some(start) = line[position..end].find(|c: char| !c.is_whitespace()) {
+            let start = position + start;
+            let length = value_length(&line[start..end]).max(1);
+            values.push(start..start + length);
+            position = start + length;
+        }
+        match values.last() {
+            Some(value) if values.len() > 1 => {
+                redacted.push_str(&line[..value.start]);
+                redacted.push('"');
+                redacted.push_str(REDACTED);
+                redacted.push('"');
+                redacted.push_str(&line[value.end..]);
+            }
+            _ => redacted.push_str(line),
+        }
+    }
+    redacted
+}
+
+/// Returns the byte ranges of the `snmp` hierarchy of an XML or text configuration and
+/// of `set snmp` statements.
+fn snmp_ranges(text: &str) -> Vec<Range<usize>> {
+    let mut ranges = vec![];
+    let mut start = 0;
+    while let Some(found) = text[start..].find("snmp") {
+        let at = start + found;
+        let after = &text[at + "snmp".len()..];
+        let offset = |rest: &str| text.len() - rest.len();
+        let is_word = text[..at].chars().next_back().is_some_and(is_word_char) || after.starts_with(is_word_char);
+        let end = if is_word {
+            None
+        } else if text[..at].ends_with('<') && after.starts_with(['>', ' ']) {
+            after.find("</snmp>").map(|end| offset(after) + end)
+        } else if let Some(block) = after.trim_start_matches([' ', '\t']).strip_prefix('{') {
+            let mut depth = 1;
+            let end = block.char_indices().find(|(_, c)| {
+                match c {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            });
+            Some(end.map_or(text.len(), |(end, _)| offset(block) + end))
+        } else if after.starts_with(' ') {
+            Some(after.find('\n').map_or(text.len(), |end| offset(after) + end))
+        } else {
+            None
+        };
+        match end {
+            Some(end) => {
+                ranges.push(at..end);
+                start = end;
+            }
+            None => start = at + "snmp".len(),
+        }
+    }
+    ranges
+}
+
+/// Replaces the values of secret statements in `text`, which may be XML, text or set
+/// configuration, by asterisks.
+///
+/// Covers `<secret>..</secret>` elements, `secret "..";` and `set .. secret ..`
+/// statements, `"secret": ".."` in JSON and the SNMP community names, as well as
+/// any statement Junos marks with `## SECRET-DATA`.
+pub fn redact(text: &str) -> String {
+    let redacted = redact_statements(&redact_secret_data(text), SECRETS);
+    let mut result = String::with_capacity(redacted.len());
+    let mut last = 0;
+    for range in snmp_ranges(&redacted) {
+        result.push_str(&redacted[last..range.start]);
+        result.push_str(&redact_statements(&redacted[range.clone()], SNMP_SECRETS));
+        last = range.end;
+    }
+    result.push_str(&redacted[last..]);
+    result
+}
+
+/// Replaces the values of the `secrets` statements in `text`.
+fn redact_statements(text: &str, secrets: &[&str]) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text;
+    'scan: while !rest.is_empty() {
+        for secret in secrets {
+            let Some(after) = rest.strip_prefix(secret) else {
+                continue;
+            };
+            let preceded_by_word = redacted.chars().next_back().is_some_and(is_word_char);
+            if preceded_by_word || after.starts_with(is_word_char) {
+                continue;
+            }
+            let leaf = LEAF_SECRETS.contains(secret);
+            if redacted.ends_with('"') && after.starts_with('"') {
+                // "secret": "value" in JSON, objects and lists are kept
+                let value = after[1..].trim_start().strip_prefix(':').map(str::trim_start);
+                if let Some(value) = value.filter(|value| value.starts_with('"')) {
+                    redacted.push_str(secret);
+                    redacted.push_str(&after[..after.len() - value.len()]);
+                    redacted.push('"');
+                    redacted.push_str(REDACTED);
+                    redacted.push('"');
+                    rest = &value[value_length(value)..];
+                    continue 'scan;
+                }
+            }
+            if redacted.ends_with('<') && after.starts_with('>') {
+                // <secret><name>value</name>, like an SNMP community
+                let children = after[1..].trim_start();
+                if let Some(name) = children.strip_prefix("<name>").filter(|_| !leaf) {
+                    let length = name.find('<').unwrap_or(name.len());
+                    if length > 0 {
+                        redacted.push_str(secret);
+                        redacted.push_str(&after[..after.len() - children.len()]);
+                        redacted.push_str("<name>");
+                        redacted.push_str(REDACTED);
+                        rest = &name[length..];
+                        continue 'scan;
+                    }
+                }
+                // <secret>value</secret>, elements with children are kept
+                let length = after[1..].find('<').unwrap_or(after.len() - 1);
+                if length > 0 {
+                    redacted.push_str(secret);
+                    redacted.push('>');
+                    redacted.push_str(REDACTED);
+                    rest = &after[1 + length..];
+                    continue 'scan;
+                }
+            }
+            let spaces = after.len() - after.trim_start_matches([' ', '\t']).len();
+            if spaces > 0 && !redacted.ends_with('/') && !redacted.ends_with('<') {
+                // secret "value"; or set ... secret value
+                let value = &after[spaces..];
+                if starts_with_secret(value, secrets) {
+                    // The value follows the second keyword
+                    redacted.push_str(secret);
+                    redacted.push_str(&after[..spaces]);
+                    rest = value;
+                    continue 'scan;
+                }
+                let length = value_length(value);
+                if length > 0 && (!leaf || ends_statement(&value[length..])) {
+                    redacted.push_str(secret);
+                    redacted.push_str(&after[..spaces]);
+                    redacted.push('"');
+                    redacted.push_str(REDACTED);
+                    redacted.push('"');
+                    rest = &value[length..];
+                    continue 'scan;
+                }
+            }
+        }
+        let mut chars = rest.chars();
+        if let Some(c) = chars.next() {
+            redacted.push(c);
+        }
+        rest = chars.as_str();
+    }
+    redacted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secret_after_secret_keyword() {
+        assert_eq!(
+            redact(r#"set security ike policy p pre-shared-key ascii-text "$9$abc""#),
+            r#"set security ike policy p pre-shared-key ascii-text "********""#
+        );
+        assert_eq!(
+            redact("pre-shared-key hexadecimal 0a1b2c;"),
+            r#"pre-shared-key hexadecimal "********";"#
+        );
+    }
+
+    #[test]
+    fn xml_elements() {
+        assert_eq!(
+            redact("<encrypted-password>$6$abc</encrypted-password>"),
+            "<encrypted-password>********</encrypted-password>"
+        );
+        assert_eq!(
+            redact("<pre-shared-key><ascii-text>$9$abc</ascii-text></pre-shared-key>"),
+            "<pre-shared-key><ascii-text>********</ascii-text></pre-shared-key>"
+        );
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(redact(r#"{"encrypted-password": "$6$abc"}"#), r#"{"encrypted-password": "********"}"#);
+        assert_eq!(
+            redact(r#"{"pre-shared-key" : {"ascii-text" : "$9$a\"bc"}}"#),
+            r#"{"pre-shared-key" : {"ascii-text" : "********"}}"#
+        );
+    }
+
+    #[test]
+    fn snmp_v3_passwords() {
+        let set = "set snmp v3 usm local-engine user u authentication-sha authentication-password \"$9$a\"\n\
+                   set snmp v3 usm local-engine user u privacy-aes128 privacy-password \"$9$b\"";
+        assert_eq!(
+            redact(set),
+            "set snmp v3 usm local-engine user u authentication-sha authentication-password \"********\"\n\
+             set snmp v3 usm local-engine user u privacy-aes128 privacy-password \"********\""
+        );
+    }
+
+    #[test]
+    fn key_values_but_not_key_names() {
+        assert_eq!(
+            redact("<md5><name>1</name><key>$9$abc</key></md5>"),
+            "<md5><name>1</name><key>********</key></md5>"
+        );
+        assert_eq!(
+            redact(r#"set protocols ospf area 0 interface ge-0/0/0 authentication md5 1 key "$9$abc""#),
+            r#"set protocols ospf area 0 interface ge-0/0/0 authentication md5 1 key "********""#
+        );
+        assert_eq!(
+            redact(r#"set security authentication-key-chains key-chain kc key 0 secret "$9$abc""#),
+            r#"set security authentication-key-chains key-chain kc key 0 secret "********""#
+        );
+        assert_eq!(
+            redact("<key><name>0</name><secret>$9$abc</secret></key>"),
+            "<key><name>0</name><secret>********</secret></key>"
+        );
+        assert_eq!(redact("key 0 {"), "key 0 {");
+    }
+
+    #[test]
+    fn secret_data_marker() {
+        let text = "user u {\n    authentication-md5 {\n        authentication-key-value \"$9$abc\"; ## SECRET-DATA\n    }\n}\n";
+        assert_eq!(
+            redact(text),
+            "user u {\n    authentication-md5 {\n        authentication-key-value \"********\"; ## SECRET-DATA\n    }\n}\n"
+        );
+    }
+
+    #[test]
+    fn communities_only_within_snmp() {
+        assert_eq!(
+            redact("set snmp community public authorization read-only\nset policy-options community c members 65000:1"),
+            "set snmp community \"********\" authorization read-only\nset policy-options community c members 65000:1"
+        );
+        assert_eq!(
+            redact("<snmp><community><name>public</name></community></snmp>"),
+            "<snmp><community><name>********</name></community></snmp>"
+        );
+    }
+}
@@ -0,0 +1,416 @@
+use std::io::{self, BufRead, BufReader, Read};
+use std::time::Instant;
+
+use quick_xml::events::{BytesStart, BytesText, Event};
+use quick_xml::{de::from_str, Reader, Writer};
+
+use super::error::{NETCONFError, NETCONFResult};
+use super::message;
+use super::xml::RPCReplyCommand;
+use super::NETCONFClient;
+
+/// Small elements of an `rpc-reply` which are collected and deserialized while streaming.
+const STREAMED_COMMANDS: &[&[u8]] = &[b"rpc-error", b"ok", b"load-configuration-results"];
+
+/// Reads the body of the current message from the transport, ending at the delimiter.
+struct MessageReader<'a> {
+    client: &'a mut NETCONFClient,
+    deadline: Option<Instant>,
+    pending: Vec<u8>,
+    position: usize,
+    done: bool,
+    /// The original error, as quick-xml only passes on an `io::Error`
+    error: Option<NETCONFError>,
+    message_id: u64,
+    sent: Instant,
+    bytes: usize,
+    /// The message so far, kept only to be traced
+    traced: Option<Vec<u8>>,
+}
+
+impl MessageReader<'_> {
+    fn log(&mut self) {
+        let xml = self.traced.take().map(|xml| String::from_utf8_lossy(&xml).into_owned());
+        message::log_streamed(Some(self.message_id), self.bytes, xml.as_deref(), Some(self.sent.elapsed()));
+    }
+}
+
+impl Read for MessageReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.pending.len() {
+            if self.done {
+                return Ok(0);
+            }
+            let (data, end) = self.client.framer.take_partial();
+            self.bytes += data.len();
+            if let Some(traced) = &mut self.traced {
+                traced.extend_from_slice(&data);
+            }
+            self.pending = data;
+            self.position = 0;
+            self.done = end;
+            if end {
+                self.log();
+            }
+            if self.pending.is_empty() && !end {
+                if let Err(e) = self.client.fill(self.deadline) {
+                    let io_error = io::Error::other(e.to_string());
+                    self.error = Some(e);
+                    return Err(io_error);
+                }
+            }
+        }
+        let bytes = buf.len().min(self.pending.len() - self.position);
+        buf[..bytes].copy_from_slice(&self.pending[self.position..self.position + bytes]);
+        self.position += bytes;
+        Ok(bytes)
+    }
+}
+
+impl Drop for MessageReader<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.client.unfinished_message = true;
+        }
+    }
+}
+
+/// An item of a streamed reply.
+#[derive(Debug)]
+pub enum ReplyEvent<'a> {
+    /// A complete `ok`, `load-configuration-results` or warning
+    Command(RPCReplyCommand),
+    /// Any other content of the `rpc-reply`, as it arrives. Text comes in pieces,
+    /// borrowed from the read buffer.
+    Event(Event<'a>),
+}
+
+/// Returns the length of the text at the start of `buffered` which can be passed on:
+/// up to the next tag, or else without an entity or a character which is continued
+/// by the next read.
+pub(super) fn text_length(buffered: &[u8]) -> usize {
+    if let Some(end) = buffered.iter().position(|b| *b == b'<') {
+        return end;
+    }
+    let mut text = buffered;
+    if let Some(entity) = text.iter().rposition(|b| *b == b'&') {
+        if !text[entity..].contains(&b';') {
+            text = &text[..entity];
+        }
+    }
+    match std::str::from_utf8(text) {
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        _ => text.len(),
+    }
+}
+
+/// Wraps a piece of text, which is still escaped.
+pub(super) fn text_event(text: &[u8]) -> NETCONFResult<Event<'_>> {
+    let text = std::str::from_utf8(text).map_err(quick_xml::Error::from)?;
+    Ok(Event::Text(BytesText::from_escaped(text)))
+}
+
+/// What a [`ParseState`] made of an event.
+pub(super) enum Step {
+    /// An item to yield
+    Item(ReplyEvent<'static>),
+    /// Nothing to yield yet
+    Continue,
+    /// The `rpc-reply` ended, the rest of the message is to be skipped
+    End,
+}
+
+/// Turns the events of a message into the items of a streamed reply, for both the
+/// blocking and the async client.
+pub(super) struct ParseState {
+    message_id: u64,
+    in_reply: bool,
+    /// Depth below `rpc-reply`
+    depth: usize,
+    /// The command being collected and its depth
+    command: Option<(Writer<Vec<u8>>, usize)>,
+    pub(super) finished: bool,
+}
+
+impl ParseState {
+    pub(super) fn new(message_id: u64) -> ParseState {
+        ParseState {
+            message_id,
+            in_reply: false,
+            depth: 0,
+            command: None,
+            finished: false,
+        }
+    }
+
+    fn check_message_id(&mut self, reply: &BytesStart) -> NETCONFResult<()> {
+        let attribute = reply.try_get_attribute("message-id")?;
+        if let Some(attribute) = attribute {
+            let id = attribute.unescape_value()?;
+            if id != self.message_id.to_string() {
+                self.finished = true;
+                return Err(NETCONFError::UnexpectedMessageId(id.into_owned()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Collects `event` into the current command, returning the command once complete.
+    fn collect(&mut self, event: Event<'static>) -> NETCONFResult<Step> {
+        let Some((writer, depth)) = &mut self.command else {
+            return Ok(Step::Continue);
+        };
+        match &event {
+            Event::Start(_) => *depth += 1,
+            Event::End(_) => *depth -= 1,
+            _ => {}
+        }
+        writer.write_event(event)?;
+        if *depth > 0 {
+            return Ok(Step::Continue);
+        }
+        let Some((writer, _)) = self.command.take() else {
+            return Ok(Step::Continue);
+        };
+        let xml = String::from_utf8(writer.into_inner())?;
+        match from_str(&xml)? {
+            RPCReplyCommand::RPCError(error) if error.error_severity != "warning" => Err(error.into()),
+            command => Ok(Step::Item(ReplyEvent::Command(command))),
+        }
+    }
+
+    /// Handles a piece of text, returning whether it is to be yielded.
+    pub(super) fn text(&mut self, text: &[u8]) -> bool {
+        match &mut self.command {
+            Some((writer, _)) => {
+                writer.get_mut().extend_from_slice(text);
+                false
+            }
+            None => self.in_reply && !self.finished,
+        }
+    }
+
+    /// Handles the next event of the message.
+    pub(super) fn step(&mut self, event: Event<'static>) -> NETCONFResult<Step> {
+        if let Event::Eof = event {
+            self.finished = true;
+            return Err(quick_xml::DeError::UnexpectedEof.into());
+        }
+        if self.command.is_some() {
+            return self.collect(event);
+        }
+        match event {
+            Event::Empty(e) if !self.in_reply && e.name().as_ref() == b"rpc-reply" => {
+                self.check_message_id(&e)?;
+                self.finished = true;
+                Ok(Step::End)
+            }
+            Event::Start(e) if !self.in_reply => {
+                self.in_reply = e.name().as_ref() == b"rpc-reply";
+                if self.in_reply {
+                    self.check_message_id(&e)?;
+                }
+                Ok(Step::Continue)
+            }
+            _ if !self.in_reply => Ok(Step::Continue),
+            Event::End(_) if self.depth == 0 => {
+                self.finished = true;
+                Ok(Step::End)
+            }
+            Event::Start(ref e) | Event::Empty(ref e)
+                if self.depth == 0 && STREAMED_COMMANDS.contains(&e.name().as_ref()) =>
+            {
+                self.command = Some((Writer::new(Vec::new()), 0));
+                self.collect(event)
+            }
+            Event::Start(_) => {
+                self.depth += 1;
+                Ok(Step::Item(ReplyEvent::Event(event)))
+            }
+            Event::End(_) => {
+                self.depth -= 1;
+                Ok(Step::Item(ReplyEvent::Event(event)))
+            }
+            event => Ok(Step::Item(ReplyEvent::Event(event))),
+        }
+    }
+}
+
+/// The reply to an RPC, parsed while it is received.
+///
+/// Only the children of `rpc-reply` are yielded. An `rpc-error` of severity error is
+/// returned as [`NETCONFError::RpcError`]. Text, like the `output` of a text
+/// command, is yielded in pieces straight from the read buffer, so that a large reply
+/// is never held in memory.
+pub struct ReplyStream<'a> {
+    reader: Reader<BufReader<MessageReader<'a>>>,
+    buffer: Vec<u8>,
+    state: ParseState,
+    /// The length of the text yielded last, still in the read buffer
+    consumed: usize,
+    /// An entity or a character which was split between two reads
+    partial: Vec<u8>,
+}
+
+impl<'a> ReplyStream<'a> {
+    /// Reads the reply to the RPC with `message_id`, which was `sent` at that time.
+    pub(super) fn new(client: &'a mut NETCONFClient, message_id: u64, sent: Instant) -> ReplyStream<'a> {
+        let deadline = client.deadline();
+        let message = MessageReader {
+            client,
+            deadline,
+            pending: vec![],
+            position: 0,
+            done: false,
+            error: None,
+            message_id,
+            sent,
+            bytes: 0,
+            traced: message::wire_traced().then(Vec::new),
+        };
+        ReplyStream {
+            reader: Reader::from_reader(BufReader::new(message)),
+            buffer: vec![],
+            state: ParseState::new(message_id),
+            consumed: 0,
+            partial: vec![],
+        }
+    }
+
+    fn take_error(&mut self, error: quick_xml::Error) -> NETCONFError {
+        match self.reader.get_mut().get_mut().error.take() {
+            Some(error) => error,
+            None => error.into(),
+        }
+    }
+
+    fn fill_buf(&mut self) -> NETCONFResult<&[u8]> {
+        if let Err(e) = self.reader.get_mut().fill_buf() {
+            return Err(self.take_error(e.into()));
+        }
+        Ok(self.reader.get_ref().buffer())
+    }
+
+    /// Reads an entity or a character which was split at the end of the read buffer
+    /// into `partial`.
+    fn read_partial(&mut self) -> NETCONFResult<()> {
+        self.partial.clear();
+        while text_length(&self.partial) < self.partial.len() || self.partial.is_empty() {
+            match self.fill_buf()?.first() {
+                Some(&byte) if byte != b'<' => self.partial.push(byte),
+                _ => break,
+            }
+            self.reader.get_mut().consume(1);
+        }
+        Ok(())
+    }
+
+    /// Reads the next tag, which quick-xml only gets to see once the text before it
+    /// was consumed.
+    fn read_event(&mut self) -> NETCONFResult<Event<'static>> {
+        self.buffer.clear();
+        match self.reader.read_event_into(&mut self.buffer) {
+            Ok(event) => Ok(event.into_owned()),
+            Err(e) => Err(self.take_error(e)),
+        }
+    }
+
+    fn finish(&mut self) -> NETCONFResult<()> {
+        let result = io::copy(self.reader.get_mut().get_mut(), &mut io::sink());
+        if let Err(e) = result {
+            return Err(self.take_error(e.into()));
+        }
+        Ok(())
+    }
+
+    /// Returns the next item of the reply, `None` after the end of `rpc-reply`.
+    pub fn next_event(&mut self) -> NETCONFResult<Option<ReplyEvent<'_>>> {
+        let consumed = std::mem::take(&mut self.consumed);
+        self.reader.get_mut().consume(consumed);
+        loop {
+            if self.state.finished {
+                return Ok(None);
+            }
+            let length = match self.fill_buf()? {
+                [] | [b'<', ..] => None,
+                buffered => Some(text_length(buffered)),
+            };
+            match length {
+                None => {
+                    let event = self.read_event()?;
+                    match self.state.step(event)? {
+                        Step::Item(item) => return Ok(Some(item)),
+                        Step::Continue => {}
+                        Step::End => {
+                            self.finish()?;
+                            return Ok(None);
+                        }
+                    }
+                }
+                Some(0) => {
+                    self.read_partial()?;
+                    if self.state.text(&self.partial) {
+                        return Ok(Some(ReplyEvent::Event(text_event(&self.partial)?)));
+                    }
+                }
+                Some(length) => {
+                    if self.state.text(&self.reader.get_ref().buffer()[..length]) {
+                        self.consumed = length;
+                        let text = &self.reader.get_ref().buffer()[..length];
+                        return Ok(Some(ReplyEvent::Event(text_event(text)?)));
+                    }
+                    self.reader.get_mut().consume(length);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quick_xml::Reader;
+
+    use super::*;
+
+    /// Runs the events of `xml` through a [`ParseState`] for `message_id`.
+    fn steps(xml: &str, message_id: u64) -> NETCONFResult<Vec<ReplyEvent<'static>>> {
+        let mut reader = Reader::from_str(xml);
+        let mut state = ParseState::new(message_id);
+        let mut items = vec![];
+        loop {
+            match state.step(reader.read_event()?.into_owned())? {
+                Step::Item(item) => items.push(item),
+                Step::Continue => {}
+                Step::End => return Ok(items),
+            }
+        }
+    }
+
+    #[test]
+    fn text_length_stops_before_split_entities_and_characters() {
+        assert_eq!(text_length(b"text<output>"), 4);
+        assert_eq!(text_length(b"a &amp; b"), 9);
+        assert_eq!(text_length(b"a &am"), 2);
+        assert_eq!(text_length(b"&am"), 0);
+        assert_eq!(text_length("a é".as_bytes()), 4);
+        assert_eq!(text_length(&"a é".as_bytes()[..3]), 2);
+    }
+
+    #[test]
+    fn empty_reply() {
+        assert!(steps(r#"<rpc-reply message-id="1"/>"#, 1).unwrap().is_empty());
+        assert!(matches!(
+            steps(r#"<rpc-reply message-id="2"/>"#, 1),
+            Err(NETCONFError::UnexpectedMessageId(id)) if id == "2"
+        ));
+    }
+
+    #[test]
+    fn commands_and_events() {
+        let items = steps(r#"<rpc-reply message-id="1"><ok/><output>text</output></rpc-reply>"#, 1).unwrap();
+        assert!(matches!(items[0], ReplyEvent::Command(RPCReplyCommand::Ok)));
+        assert!(matches!(&items[1], ReplyEvent::Event(Event::Start(e)) if e.name().as_ref() == b"output"));
+        assert!(matches!(&items[2], ReplyEvent::Event(Event::Text(text)) if text.as_ref() == b"text"));
+        assert_eq!(items.len(), 4);
+    }
+}
@@ -0,0 +1,25 @@
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+/// A byte stream carrying NETCONF messages, e.g. an SSH channel.
+pub trait Transport: Read + Write {
+    /// Limits how long a single read may block, `None` blocks until data arrives.
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()>;
+
+    /// Sends a keepalive if one is due and returns when the next one is needed.
+    ///
+    /// Transports without keepalives return `None`.
+    fn keepalive(&mut self) -> io::Result<Option<Duration>> {
+        Ok(None)
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        (**self).set_read_timeout(timeout)
+    }
+
+    fn keepalive(&mut self) -> io::Result<Option<Duration>> {
+        (**self).keepalive()
+    }
+}
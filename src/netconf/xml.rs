@@ -1,5 +1,11 @@
+use std::borrow::Cow;
 use std::fmt::Display;
 
+use quick_xml::{
+    de::from_str,
+    events::Event,
+    DeError, Reader, Writer,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -21,6 +27,8 @@ pub struct Capabilities {
     pub capability: Vec<String>,
 }
 
+/// An `rpc` element wrapping a command. The clients frame requests themselves, adding
+/// the message-id, so this is only needed to build RPCs by hand.
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename = "rpc")]
 #[serde(deny_unknown_fields)]
@@ -38,9 +46,11 @@ pub enum RPCCommand {
         format: String,
 
         #[serde(rename = "@rollback")]
+        #[serde(skip_serializing_if = "Option::is_none")]
         rollback: Option<String>,
 
         #[serde(rename = "@compare")]
+        #[serde(skip_serializing_if = "Option::is_none")]
         compare: Option<String>,
     },
     #[serde(rename = "command")]
@@ -57,6 +67,9 @@ pub enum RPCCommand {
     #[serde(rename = "unlock-configuration")]
     UnlockConfiguration {},
 
+    #[serde(rename = "get-commit-information")]
+    GetCommitInformation {},
+
     #[serde(rename = "load-configuration")]
     LoadConfiguration {
         #[serde(rename = "@format")]
@@ -74,21 +87,82 @@ pub enum RPCCommand {
         cfg_set: Option<String>,
     },
 
-    #[serde(rename = "commit-configuration")]
-    CommitConfiguration {},
+    #[serde(rename = "discard-changes")]
+    DiscardChanges {},
 
+    /// The Junos commit, confirmed if `confirmed` is set or scheduled for `at_time`,
+    /// like `2026-11-01 02:00`.
     #[serde(rename = "commit-configuration")]
-    CommitConfirmedConfiguration {
-        confirmed: ConfigurationConfirmed,
+    CommitConfiguration {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        check: Option<Flag>,
+
+        #[serde(skip_serializing_if = "Option::is_none")]
+        log: Option<String>,
+
+        #[serde(skip_serializing_if = "Option::is_none")]
+        synchronize: Option<Flag>,
+
+        #[serde(rename = "force-synchronize")]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        force_synchronize: Option<Flag>,
+
+        #[serde(skip_serializing_if = "Option::is_none")]
+        confirmed: Option<ConfigurationConfirmed>,
 
         #[serde(rename = "confirm-timeout")]
-        confirm_timeout: i32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        confirm_timeout: Option<i32>,
+
+        #[serde(rename = "at-time")]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        at_time: Option<String>,
+    },
+
+    /// The `commit` of RFC 6241, used for confirmed commits with `persist`.
+    #[serde(rename = "commit")]
+    Commit {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        confirmed: Option<ConfigurationConfirmed>,
+
+        /// In seconds, unlike the minutes of `commit-configuration`
+        #[serde(rename = "confirm-timeout")]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        confirm_timeout: Option<u32>,
+
+        #[serde(skip_serializing_if = "Option::is_none")]
+        persist: Option<String>,
+
+        #[serde(rename = "persist-id")]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        persist_id: Option<String>,
+    },
+
+    #[serde(rename = "cancel-commit")]
+    CancelCommit {
+        #[serde(rename = "persist-id")]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        persist_id: Option<String>,
     },
 }
 
+/// Loads a previous configuration into the candidate. Not part of [`RPCCommand`], as
+/// it shares the `load-configuration` element with [`RPCCommand::LoadConfiguration`].
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename = "load-configuration")]
+#[serde(deny_unknown_fields)]
+pub struct RollbackConfiguration {
+    #[serde(rename = "@rollback")]
+    pub rollback: u32,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ConfigurationConfirmed {}
 
+/// An empty element switching an option on, like `<synchronize/>`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Flag {}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename = "rpc-reply")]
 pub struct RPCReply {
@@ -114,6 +188,12 @@ pub enum RPCReplyCommand {
         configuration_output: String,
     },
 
+    #[serde(rename = "configuration-text")]
+    ConfigurationText(ConfigurationData),
+
+    #[serde(rename = "configuration-set")]
+    ConfigurationSet(ConfigurationData),
+
     #[serde(rename = "rpc-error")]
     RPCError(RPCError),
 
@@ -122,6 +202,171 @@ pub enum RPCReplyCommand {
 
     #[serde(rename = "$text")]
     Other(String),
+
+    /// Any element without a dedicated variant, kept as raw XML.
+    #[serde(skip)]
+    Xml(String),
+}
+
+/// Elements of an `rpc-reply` which are deserialized into a dedicated [`RPCReplyCommand`].
+const KNOWN_REPLY_ELEMENTS: &[&[u8]] = &[
+    b"output",
+    b"load-configuration-results",
+    b"configuration-information",
+    b"configuration-text",
+    b"configuration-set",
+    b"rpc-error",
+    b"ok",
+];
+
+impl RPCReply {
+    /// Parses an `rpc-reply`, keeping unknown child elements as [`RPCReplyCommand::Xml`].
+    pub fn from_xml(xml: &str) -> Result<RPCReply, DeError> {
+        let mut reader = Reader::from_str(xml);
+        reader.config_mut().trim_text(true);
+
+        let mut rpc_reply = vec![];
+        let mut in_reply = false;
+        loop {
+            let start = reader.buffer_position() as usize;
+            match reader.read_event()? {
+                Event::Start(e) if !in_reply && e.name().as_ref() == b"rpc-reply" => in_reply = true,
+                Event::Empty(e) if !in_reply && e.name().as_ref() == b"rpc-reply" => break,
+                Event::End(e) if in_reply && e.name().as_ref() == b"rpc-reply" => break,
+                Event::Start(e) if in_reply => {
+                    let name = e.name().as_ref().to_vec();
+                    reader.read_to_end(e.name())?;
+                    let raw = xml[start..reader.buffer_position() as usize].trim();
+                    rpc_reply.push(Self::parse_element(&name, raw)?);
+                }
+                Event::Empty(e) if in_reply => {
+                    let raw = xml[start..reader.buffer_position() as usize].trim();
+                    rpc_reply.push(Self::parse_element(e.name().as_ref(), raw)?);
+                }
+                Event::Text(text) if in_reply => {
+                    rpc_reply.push(RPCReplyCommand::Other(text.unescape()?.into_owned()));
+                }
+                Event::CData(data) if in_reply => {
+                    rpc_reply.push(RPCReplyCommand::Other(
+                        String::from_utf8_lossy(&data.into_inner()).into_owned(),
+                    ));
+                }
+                Event::Eof => return Err(DeError::UnexpectedEof),
+                _ => {}
+            }
+        }
+
+        Ok(RPCReply { rpc_reply })
+    }
+
+    /// Returns the `message-id` attribute of an `rpc-reply`, if present.
+    pub fn message_id(xml: &str) -> Result<Option<String>, DeError> {
+        let mut reader = Reader::from_str(xml);
+        loop {
+            match reader.read_event()? {
+                Event::Start(e) | Event::Empty(e) if e.name().as_ref() == b"rpc-reply" => {
+                    let attribute = e.try_get_attribute("message-id")?;
+                    return match attribute {
+                        Some(attribute) => Ok(Some(attribute.unescape_value()?.into_owned())),
+                        None => Ok(None),
+                    };
+                }
+                Event::Eof => return Err(DeError::UnexpectedEof),
+                _ => {}
+            }
+        }
+    }
+
+    fn parse_element(name: &[u8], raw: &str) -> Result<RPCReplyCommand, DeError> {
+        if KNOWN_REPLY_ELEMENTS.contains(&name) {
+            from_str(raw)
+        } else {
+            Ok(RPCReplyCommand::Xml(raw.to_string()))
+        }
+    }
+}
+
+/// Re-indents an XML fragment for display.
+pub fn pretty_print(xml: &str) -> Result<String, quick_xml::Error> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+    let mut writer = Writer::new_with_indent(Vec::new(), b' ', 2);
+    loop {
+        match reader.read_event()? {
+            Event::Eof => break,
+            event => writer.write_event(event)?,
+        }
+    }
+    Ok(String::from_utf8_lossy(&writer.into_inner()).into_owned())
+}
+
+/// Returns the name of the root element of a message.
+pub fn root_element(xml: &str) -> Result<String, quick_xml::Error> {
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) => {
+                return Ok(String::from_utf8_lossy(e.local_name().as_ref()).into_owned())
+            }
+            Event::Eof => return Ok(String::new()),
+            _ => {}
+        }
+    }
+}
+
+/// Returns the content of `body` if it is a whole `rpc` element, which the clients
+/// add themselves, and `body` otherwise.
+pub fn rpc_content(body: &str) -> Result<Cow<'_, str>, quick_xml::Error> {
+    let mut reader = Reader::from_str(body);
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) if e.local_name().as_ref() == b"rpc" => {
+                let end = e.to_end().into_owned();
+                return reader.read_text(end.name());
+            }
+            Event::Empty(e) if e.local_name().as_ref() == b"rpc" => return Ok(Cow::Borrowed("")),
+            Event::Start(_) | Event::Empty(_) | Event::Eof => return Ok(Cow::Borrowed(body)),
+            _ => {}
+        }
+    }
+}
+
+/// An event notification (RFC 5277).
+#[derive(Debug)]
+pub struct Notification {
+    pub event_time: String,
+    /// The whole `notification` element
+    pub xml: String,
+}
+
+impl Notification {
+    pub fn from_xml(xml: String) -> Result<Notification, quick_xml::Error> {
+        let mut reader = Reader::from_str(&xml);
+        reader.config_mut().trim_text(true);
+        let mut event_time = String::new();
+        let mut in_event_time = false;
+        loop {
+            match reader.read_event()? {
+                Event::Start(e) => in_event_time = e.local_name().as_ref() == b"eventTime",
+                Event::Text(text) if in_event_time => {
+                    event_time = text.unescape()?.into_owned();
+                    break;
+                }
+                Event::End(_) => in_event_time = false,
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        Ok(Notification { event_time, xml })
+    }
+}
+
+// Junos decorates these with namespace and commit metadata attributes,
+// so unknown fields are accepted here.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ConfigurationData {
+    #[serde(rename = "$text", default)]
+    pub text: String,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -151,6 +396,55 @@ pub struct LoadErrorCount {
     message: String,
 }
 
+impl LoadErrorCount {
+    /// The number of errors, unreadable counts are taken as one.
+    pub fn count(&self) -> u32 {
+        self.message.trim().parse().unwrap_or(1)
+    }
+}
+
+/// Reply to `get-commit-information`, the RPC of `show system commit`.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename = "commit-information")]
+pub struct CommitInformation {
+    /// Commits scheduled with `commit at`
+    #[serde(rename = "commit-pending", default)]
+    pub pending: Vec<CommitEntry>,
+
+    #[serde(rename = "commit-history", default)]
+    pub history: Vec<CommitEntry>,
+}
+
+// Junos adds the time in seconds as attribute of `date-time` and more fields
+// depending on the release, so unknown fields are accepted here.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CommitEntry {
+    #[serde(rename = "sequence-number")]
+    pub sequence_number: Option<u32>,
+    pub user: Option<String>,
+    pub client: Option<String>,
+    #[serde(rename = "date-time")]
+    pub date_time: Option<String>,
+    pub comment: Option<String>,
+}
+
+impl Display for CommitEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let field = |value: &Option<String>| value.clone().unwrap_or_else(|| "?".to_string());
+        write!(
+            f,
+            "commit requested by {} via {} at {}",
+            field(&self.user),
+            field(&self.client),
+            field(&self.date_time)
+        )?;
+        if let Some(comment) = &self.comment {
+            write!(f, ": {}", comment)?;
+        }
+        Ok(())
+    }
+}
+
 impl Display for RPCReply {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for reply in &self.rpc_reply {
@@ -167,7 +461,7 @@ impl Display for RPCReplyCommand {
             RPCReplyCommand::Output { text } => {
                 write!(f, "{}", text)
             }
-            RPCReplyCommand::Other(text) => {
+            RPCReplyCommand::Other(text) | RPCReplyCommand::Xml(text) => {
                 write!(f, "{}", text)
             }
             RPCReplyCommand::ConfigurationInformation {
@@ -175,6 +469,9 @@ impl Display for RPCReplyCommand {
             } => {
                 write!(f, "{}", configuration_information)
             }
+            RPCReplyCommand::ConfigurationText(data) | RPCReplyCommand::ConfigurationSet(data) => {
+                write!(f, "{}", data.text)
+            }
             RPCReplyCommand::Ok => {
                 write!(f, "Executed Successfully!")
             }
@@ -185,7 +482,7 @@ impl Display for RPCReplyCommand {
                 for elem in &x.load_configuration_results {
                     match elem {
                         LoadConfigurationResultsEnum::Ok => {
-                            writeln!(f, "{}", "Success!")?;
+                            writeln!(f, "Success!")?;
                         }
                         LoadConfigurationResultsEnum::RPCError(error) => {
                             writeln!(f, "{}", error)?;
@@ -251,9 +548,32 @@ impl Display for RPCError {
                 error_info.bad_element
             )?;
         }
-        write!(f, "")?;
         Ok(())
     }
 }
 
 impl std::error::Error for RPCError { }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_reply() {
+        assert!(RPCReply::from_xml(r#"<rpc-reply message-id="1"/>"#).unwrap().rpc_reply.is_empty());
+        assert!(RPCReply::from_xml(r#"<rpc-reply message-id="1"></rpc-reply>"#).unwrap().rpc_reply.is_empty());
+        assert_eq!(RPCReply::message_id(r#"<rpc-reply message-id="1"/>"#).unwrap().as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn unknown_elements_are_kept() {
+        let reply = RPCReply::from_xml(r#"<rpc-reply message-id="1"><ok/><route-information><x/></route-information></rpc-reply>"#).unwrap();
+        assert!(matches!(reply.rpc_reply[0], RPCReplyCommand::Ok));
+        assert!(matches!(&reply.rpc_reply[1], RPCReplyCommand::Xml(xml) if xml == "<route-information><x/></route-information>"));
+    }
+
+    #[test]
+    fn unterminated_reply() {
+        assert!(RPCReply::from_xml(r#"<rpc-reply message-id="1"><ok/>"#).is_err());
+    }
+}
@@ -0,0 +1,278 @@
+use std::env;
+use std::path::PathBuf;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use rucli::netconf::error::NETCONFResult;
+use rucli::netconf::{CommitOptions, NETCONFClient, Outcome};
+
+use crate::guardrails::Guardrails;
+use crate::warnings::WarningPolicy;
+
+const COMMANDS: &[&str] = &[
+    "show | compare",
+    "show ",
+    "set ",
+    "delete ",
+    "commit",
+    "commit confirmed ",
+    "commit comment ",
+    "commit synchronize",
+    "commit and-quit",
+    "rollback ",
+    "help",
+    "exit",
+];
+
+const HELP: &str = "\
+<command>               run an operational command
+set <statement>         add a statement to the candidate configuration
+delete <statement>      remove a statement from the candidate configuration
+show | compare          show the pending candidate changes
+commit [options]        commit the candidate, with the options
+  confirmed [N]           roll back unless confirmed within N minutes
+  comment \"text\"          log a comment with the commit
+  synchronize             commit on both routing engines
+  force-synchronize       synchronize even if the other routing engine is locked
+  and-quit                leave the shell after the commit
+rollback N              load rollback N into the candidate
+<rpc-body/>             send a raw RPC and print the reply
+exit                    leave the shell and release the configuration lock";
+
+struct ShellHelper;
+
+impl Completer for ShellHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let prefix = &line[..pos];
+        let candidates = COMMANDS
+            .iter()
+            .filter(|command| command.starts_with(prefix))
+            .map(|command| command.to_string())
+            .collect();
+        Ok((0, candidates))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+/// Holds the state of an interactive session on top of one NETCONF connection.
+struct Shell<'a> {
+    client: &'a mut NETCONFClient,
+    policy: &'a WarningPolicy,
+    locked: bool,
+    /// Set by `commit and-quit`
+    quit: bool,
+    guardrails: Option<&'a Guardrails>,
+    allow_protected: bool,
+}
+
+/// Splits off the first word of `text`, or the text in double quotes if it starts
+/// with one.
+fn next_word(text: &str) -> Option<(&str, &str)> {
+    let text = text.trim_start();
+    if let Some(quoted) = text.strip_prefix('"') {
+        let end = quoted.find('"')?;
+        return Some((&quoted[..end], &quoted[end + 1..]));
+    }
+    let end = text.find(char::is_whitespace).unwrap_or(text.len());
+    (end > 0).then(|| (&text[..end], &text[end..]))
+}
+
+impl Shell<'_> {
+    fn check<T>(&self, result: NETCONFResult<Outcome<T>>) -> Result<T, String> {
+        let outcome = result.map_err(|e| e.to_string())?;
+        self.policy.check(outcome).map_err(|e| e.to_string())
+    }
+
+    fn lock(&mut self) -> Result<(), String> {
+        if !self.locked {
+            let result = self.client.lock_configuration();
+            self.check(result)?;
+            self.locked = true;
+        }
+        Ok(())
+    }
+
+    fn unlock(&mut self) -> Result<(), String> {
+        if self.locked {
+            let result = self.client.unlock_configuration();
+            self.check(result)?;
+            self.locked = false;
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, line: &str) -> Result<Option<String>, String> {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("help") => Ok(Some(HELP.to_string())),
+            Some("set") | Some("delete") => {
+                self.lock()?;
+                let result = self
+                    .client
+                    .load_configuration(line.to_string(), "set".into(), "set".into());
+                self.check(result)?;
+                Ok(None)
+            }
+            Some("show") if line.replace(' ', "") == "show|compare" => {
+                let result = self.client.diff_configuration("text".to_string());
+                Ok(Some(self.check(result)?))
+            }
+            Some("commit") => {
+                let mut confirm_timeout = None;
+                let mut options = CommitOptions::default();
+                let mut and_quit = false;
+                let mut rest = line.trim_start().strip_prefix("commit").unwrap_or_default();
+                while let Some((option, after)) = next_word(rest) {
+                    rest = after;
+                    match option {
+                        "confirmed" => {
+                            confirm_timeout = Some(10);
+                            if let Some((minutes, after)) = next_word(rest).filter(|(w, _)| w.starts_with(|c: char| c.is_ascii_digit())) {
+                                confirm_timeout = Some(minutes.parse().map_err(|_| format!("Invalid timeout: {}", minutes))?);
+                                rest = after;
+                            }
+                        }
+                        "comment" => {
+                            let (comment, after) = next_word(rest).ok_or("Missing comment")?;
+                            options.comment = Some(comment.to_string());
+                            rest = after;
+                        }
+                        "synchronize" => options.synchronize = true,
+                        "force-synchronize" => options.force_synchronize = true,
+                        "and-quit" => and_quit = true,
+                        other => return Err(format!("Unknown commit option: {}", other)),
+                    }
+                }
+                self.lock()?;
+                if let Some(guardrails) = self.guardrails {
+                    let result = self.client.diff_configuration("text".to_string());
+                    let diff = self.check(result)?;
+                    guardrails
+                        .check(&diff, self.allow_protected)
+                        .map_err(|e| e.to_string())?;
+                }
+                let result = self.client.apply_configuration(confirm_timeout, None, &options);
+                let results = self.check(result)?;
+                self.unlock()?;
+                self.quit = and_quit;
+                if results.is_empty() {
+                    Ok(Some("commit complete".to_string()))
+                } else {
+                    Ok(Some(format!("{}\ncommit complete", results.trim_end())))
+                }
+            }
+            Some("rollback") => {
+                let rollback = match words.next() {
+                    None => 0,
+                    Some(n) => n.parse().map_err(|_| format!("Invalid rollback: {}", n))?,
+                };
+                self.lock()?;
+                let result = self.client.rollback_configuration(rollback);
+                self.check(result)?;
+                Ok(Some("load complete".to_string()))
+            }
+            Some(_) if line.starts_with('<') => {
+                if let Some(guardrails) = self.guardrails {
+                    guardrails.check_rpc(line).map_err(|e| e.to_string())?;
+                }
+                let result = self.client.send_raw_rpc(line);
+                Ok(Some(self.check(result)?))
+            }
+            Some(_) => {
+                let result = self.client.send_command(line.to_string(), "text".to_string());
+                Ok(Some(self.check(result)?))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    env::var("HOME")
+        .ok()
+        .map(|home| PathBuf::from(home).join(".rucli_history"))
+}
+
+/// Runs an interactive shell on the given session until the user exits.
+pub fn run(
+    client: &mut NETCONFClient,
+    hostname: &str,
+    policy: &WarningPolicy,
+    guardrails: Option<&Guardrails>,
+    allow_protected: bool,
+) -> rustyline::Result<()> {
+    let mut editor: Editor<ShellHelper, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(ShellHelper));
+
+    let history = history_path();
+    if let Some(history) = &history {
+        let _ = editor.load_history(history);
+    }
+
+    let mut shell = Shell {
+        client,
+        policy,
+        locked: false,
+        quit: false,
+        guardrails,
+        allow_protected,
+    };
+
+    loop {
+        let prompt = if shell.locked {
+            format!("{}# ", hostname)
+        } else {
+            format!("{}> ", hostname)
+        };
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line)?;
+
+        if line == "exit" || line == "quit" {
+            break;
+        }
+
+        match shell.execute(line) {
+            Ok(Some(output)) => println!("{}", output.trim_end()),
+            Ok(None) => {}
+            Err(e) => eprintln!("error: {}", e),
+        }
+        if shell.quit {
+            break;
+        }
+    }
+
+    if let Err(e) = shell.unlock() {
+        eprintln!("error: {}", e);
+    }
+    if let Some(history) = &history {
+        let _ = editor.save_history(history);
+    }
+
+    Ok(())
+}
@@ -1,16 +1,25 @@
-use std::net::TcpStream;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
 
 use ssh2::{Session, TraceFlags};
 
 use self::error::SSHError;
+use crate::netconf::transport::Transport;
 
 pub mod error;
+#[cfg(feature = "async")]
+pub mod process;
 
 pub struct SSHConnection {
     pub user: String,
     pub password: Option<String>,
     pub target: String,
     pub debug: bool,
+    /// Limits connecting, the SSH handshake and authentication
+    pub connect_timeout: Option<Duration>,
+    /// Interval of SSH keepalives in seconds, 0 disables them
+    pub keepalive_interval: u32,
 
     pub sess: Option<ssh2::Session>,
     pub channel: Option<ssh2::Channel>,
@@ -18,36 +27,129 @@ pub struct SSHConnection {
 
 impl SSHConnection {
     pub fn new(user: &str, password: Option<String>, target: &str, debug: bool) -> SSHConnection {
-        return SSHConnection {
+        SSHConnection {
             user: String::from(user),
-            password: password,
+            password,
             target: String::from(target),
             debug,
+            connect_timeout: None,
+            keepalive_interval: 0,
             sess: None,
             channel: None,
-        };
+        }
     }
 
     pub fn connect(&mut self) -> Result<(), SSHError> {
-        let tcp = TcpStream::connect(self.target.as_str())?;
+        let tcp = self.connect_tcp()?;
         let mut sess = Session::new()?;
         sess.set_tcp_stream(tcp);
         if self.debug {
             sess.trace(TraceFlags::AUTH | TraceFlags::KEX | TraceFlags::PUBLICKEY);
         };
-        sess.handshake()?;
-        if self.password.is_some() {
-            sess.userauth_password(self.user.as_str(), self.password.clone().unwrap().as_str())?;
-        } else {
-            sess.userauth_agent(self.user.as_str())?;
+        if let Some(timeout) = self.connect_timeout {
+            sess.set_timeout(timeout.as_millis().try_into().unwrap_or(u32::MAX));
         }
+        sess.handshake().map_err(|e| self.timeout_error(e))?;
+        let auth = match &self.password {
+            Some(password) => sess.userauth_password(self.user.as_str(), password.as_str()),
+            None => sess.userauth_agent(self.user.as_str()),
+        };
+        auth.map_err(|source| match self.timeout_error(source) {
+            SSHError::SSHError(source) => SSHError::AuthenticationFailed {
+                user: self.user.clone(),
+                source,
+            },
+            timeout => timeout,
+        })?;
+
+        let mut channel = sess.channel_session().map_err(|e| self.timeout_error(e))?;
+        channel.subsystem("netconf").map_err(|e| self.timeout_error(e))?;
 
-        let mut channel = sess.channel_session()?;
-        channel.subsystem("netconf")?;
+        sess.set_timeout(0);
+        sess.set_keepalive(false, self.keepalive_interval);
 
         self.sess = Some(sess);
         self.channel = Some(channel);
 
-        return Ok(());
+        Ok(())
+    }
+
+    fn connect_tcp(&self) -> Result<TcpStream, SSHError> {
+        let Some(timeout) = self.connect_timeout else {
+            return Ok(TcpStream::connect(self.target.as_str())?);
+        };
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "No address found");
+        for addr in self.target.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(tcp) => return Ok(tcp),
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => return Err(SSHError::Timeout(timeout)),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error.into())
+    }
+
+    fn timeout_error(&self, err: ssh2::Error) -> SSHError {
+        match (err.code(), self.connect_timeout) {
+            // LIBSSH2_ERROR_TIMEOUT
+            (ssh2::ErrorCode::Session(-9), Some(timeout)) => SSHError::Timeout(timeout),
+            _ => SSHError::SSHError(err),
+        }
+    }
+
+    /// Hands out the NETCONF channel of an established connection.
+    pub fn transport(&mut self) -> Result<SSHTransport, SSHError> {
+        match (&self.sess, self.channel.take()) {
+            (Some(sess), Some(channel)) => Ok(SSHTransport {
+                sess: sess.clone(),
+                channel,
+                read_timeout_ms: 0,
+            }),
+            _ => Err(SSHError::NotConnected),
+        }
+    }
+}
+
+/// The NETCONF subsystem channel of an SSH session.
+pub struct SSHTransport {
+    sess: ssh2::Session,
+    channel: ssh2::Channel,
+    /// The session timeout for reads, 0 blocks
+    read_timeout_ms: u32,
+}
+
+impl Read for SSHTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // The timeout is session wide, so it is only set for the read and writes block.
+        self.sess.set_timeout(self.read_timeout_ms);
+        let result = self.channel.read(buf);
+        self.sess.set_timeout(0);
+        result
+    }
+}
+
+impl Write for SSHTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.channel.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.channel.flush()
+    }
+}
+
+impl Transport for SSHTransport {
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.read_timeout_ms = timeout.map_or(0, |timeout| timeout.as_millis().clamp(1, u32::MAX as u128) as u32);
+        Ok(())
+    }
+
+    fn keepalive(&mut self) -> io::Result<Option<Duration>> {
+        let next = self.sess.keepalive_send()?;
+        if next == 0 {
+            // keepalives are disabled
+            return Ok(None);
+        }
+        Ok(Some(Duration::from_secs(next.into())))
     }
 }
@@ -0,0 +1,100 @@
+//! An async transport running the system `ssh` client, available with the `async` feature.
+
+use std::io;
+use std::pin::Pin;
+use std::process::Stdio;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+
+use super::error::SSHError;
+
+/// The `netconf` subsystem of an `ssh` child process.
+///
+/// Authentication is left to `ssh`, i.e. keys, the agent and `~/.ssh/config` apply.
+/// Password prompts are disabled, as nobody could answer them.
+pub struct SSHProcess {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: ChildStdout,
+}
+
+/// Splits the port off `target`, defaulting to 830.
+///
+/// An IPv6 address needs brackets to take a port, a bare one is taken as host.
+fn split_port(target: &str) -> (&str, &str) {
+    if let Some(rest) = target.strip_prefix('[') {
+        if let Some((host, port)) = rest.split_once(']') {
+            return (host, port.strip_prefix(':').filter(|port| !port.is_empty()).unwrap_or("830"));
+        }
+    }
+    match target.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') => (host, port),
+        _ => (target, "830"),
+    }
+}
+
+impl SSHProcess {
+    /// Starts `ssh` for `target`, given as `host`, `host:port` or `[address]:port`.
+    ///
+    /// A `keepalive_interval` of 0 disables keepalives.
+    pub fn spawn(
+        user: Option<&str>,
+        target: &str,
+        connect_timeout: Option<Duration>,
+        keepalive_interval: u32,
+    ) -> Result<SSHProcess, SSHError> {
+        let (host, port) = split_port(target);
+
+        let mut command = Command::new("ssh");
+        command
+            .args(["-s", "-p", port, "-o", "BatchMode=yes"])
+            .arg("-o")
+            .arg(format!("ServerAliveInterval={}", keepalive_interval));
+        if let Some(timeout) = connect_timeout {
+            command
+                .arg("-o")
+                .arg(format!("ConnectTimeout={}", timeout.as_secs().max(1)));
+        }
+        if let Some(user) = user {
+            command.args(["-l", user]);
+        }
+        command
+            .args([host, "netconf"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true);
+
+        let mut child = command.spawn()?;
+        let stdin = child.stdin.take().ok_or(SSHError::NotConnected)?;
+        let stdout = child.stdout.take().ok_or(SSHError::NotConnected)?;
+        Ok(SSHProcess { child, stdin, stdout })
+    }
+
+    /// Waits for `ssh` to exit, e.g. after the session was closed.
+    pub async fn wait(&mut self) -> io::Result<std::process::ExitStatus> {
+        self.child.wait().await
+    }
+}
+
+impl AsyncRead for SSHProcess {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stdout).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for SSHProcess {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stdin).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stdin).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stdin).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,287 @@
+//! Parsing of Junos configuration statements like `set interfaces ge-0/0/0 description "a; b"`.
+
+use std::fmt;
+
+/// The configuration mode commands that can be loaded with `configuration-set`.
+const VERBS: &[&str] = &["set", "delete", "deactivate", "activate", "rename", "insert", "annotate"];
+
+/// A statement which could not be parsed, `line` is 1-based.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[error("line {line}: {message}")]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// A configuration statement split into words, with quotes and escapes resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statement {
+    line: usize,
+    /// Never empty
+    words: Vec<String>,
+}
+
+impl Statement {
+    /// The 1-based line the statement starts on.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The command followed by its arguments.
+    pub fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    /// The command, like `set` or `delete`.
+    pub fn verb(&self) -> &str {
+        &self.words[0]
+    }
+
+    /// The words after the command.
+    pub fn arguments(&self) -> &[String] {
+        &self.words[1..]
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let arguments = self.arguments();
+        let has = |word: &str| arguments.iter().any(|argument| argument == word);
+        match self.verb() {
+            verb if !VERBS.contains(&verb) => Err(format!("unknown command {:?}, expected one of {}", verb, VERBS.join(", "))),
+            verb if arguments.is_empty() => Err(format!("{} requires a statement", verb)),
+            "rename" if !has("to") => Err("rename requires `to <name>`".to_string()),
+            "insert" if !has("before") && !has("after") => Err("insert requires `before` or `after`".to_string()),
+            "annotate" if arguments.len() < 2 => Err("annotate requires a comment".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Quotes a word if the CLI would otherwise split it or read it differently.
+fn quote(word: &str) -> String {
+    let plain = !word.is_empty()
+        && !word
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\\' | ';' | '#' | '{' | '}'));
+    if plain {
+        return word.to_string();
+    }
+    let mut quoted = String::with_capacity(word.len() + 2);
+    quoted.push('"');
+    let mut chars = word.chars().peekable();
+    while let Some(c) = chars.next() {
+        // Other backslashes are kept as is, like the ones of regular expressions
+        let escape = c == '"' || (c == '\\' && matches!(chars.peek(), None | Some('"') | Some('\\')));
+        if escape {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let words: Vec<String> = self.words.iter().map(|word| quote(word)).collect();
+        write!(f, "{}", words.join(" "))
+    }
+}
+
+/// Splits `input` into statements, separated by newlines or `;` outside of quotes.
+///
+/// Double quoted words may contain whitespace and `;`, a backslash escapes the next
+/// character. Lines starting with `#` are comments.
+pub fn parse(input: &str) -> Result<Vec<Statement>, ParseError> {
+    let (statements, errors) = parse_all(input);
+    match errors.into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(statements),
+    }
+}
+
+/// Like [`parse`], but carries on after an invalid statement and returns all errors.
+pub fn parse_all(input: &str) -> (Vec<Statement>, Vec<ParseError>) {
+    let mut statements = vec![];
+    let mut errors = vec![];
+    let mut words: Vec<String> = vec![];
+    let mut word: Option<String> = None;
+    let mut line = 1;
+    let mut start = 1;
+    // The line the open quote is on
+    let mut quoted: Option<usize> = None;
+    let mut comment = false;
+    let mut chars = input.chars();
+
+    let mut finish = |words: &mut Vec<String>, start: usize, errors: &mut Vec<ParseError>| {
+        if words.is_empty() {
+            return;
+        }
+        let statement = Statement {
+            line: start,
+            words: std::mem::take(words),
+        };
+        match statement.validate() {
+            Ok(()) => statements.push(statement),
+            Err(message) => errors.push(ParseError { line: start, message }),
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        if comment {
+            if c == '\n' {
+                comment = false;
+                line += 1;
+            }
+            continue;
+        }
+        if words.is_empty() && word.is_none() && !c.is_whitespace() && c != ';' {
+            start = line;
+        }
+        match c {
+            '\\' => {
+                let Some(escaped) = chars.next() else {
+                    errors.push(ParseError {
+                        line,
+                        message: "trailing backslash".to_string(),
+                    });
+                    break;
+                };
+                if escaped == '\n' {
+                    line += 1;
+                    if quoted.is_none() {
+                        // A statement continued on the next line
+                        words.extend(word.take());
+                        continue;
+                    }
+                }
+                let word = word.get_or_insert_with(String::new);
+                if quoted.is_some() && escaped != '"' && escaped != '\\' {
+                    // Kept for regular expressions like "^\d+$"
+                    word.push('\\');
+                }
+                word.push(escaped);
+            }
+            '"' if quoted.is_some() => quoted = None,
+            '"' => {
+                quoted = Some(line);
+                word.get_or_insert_with(String::new);
+            }
+            c if quoted.is_some() => {
+                if c == '\n' {
+                    line += 1;
+                }
+                word.get_or_insert_with(String::new).push(c);
+            }
+            '#' if words.is_empty() && word.is_none() => comment = true,
+            c if c.is_whitespace() || c == ';' => {
+                words.extend(word.take());
+                if c == '\n' || c == ';' {
+                    finish(&mut words, start, &mut errors);
+                }
+                if c == '\n' {
+                    line += 1;
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if let Some(line) = quoted {
+        errors.push(ParseError {
+            line,
+            message: "unterminated quote".to_string(),
+        });
+        return (statements, errors);
+    }
+    words.extend(word.take());
+    finish(&mut words, start, &mut errors);
+    (statements, errors)
+}
+
+/// Renders statements as the text of a `configuration-set`, one per line.
+pub fn to_configuration_set(statements: &[Statement]) -> String {
+    statements
+        .iter()
+        .map(|statement| statement.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(statement: &Statement) -> Vec<&str> {
+        statement.words().iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn semicolon_in_quotes() {
+        let statements = parse(r#"set interfaces ge-0/0/0 description "a; b""#).unwrap();
+        assert_eq!(statements.len(), 1);
+        assert_eq!(words(&statements[0]), ["set", "interfaces", "ge-0/0/0", "description", "a; b"]);
+        assert_eq!(statements[0].to_string(), r#"set interfaces ge-0/0/0 description "a; b""#);
+    }
+
+    #[test]
+    fn regex_backslashes_are_kept() {
+        let input = r#"set policy-options as-path-group g as-path p "^\d+$""#;
+        let statements = parse(input).unwrap();
+        assert_eq!(statements[0].arguments().last().unwrap(), r"^\d+$");
+        assert_eq!(statements[0].to_string(), input);
+
+        let statements = parse(r#"set system login message "\d""#).unwrap();
+        assert_eq!(statements[0].arguments().last().unwrap(), r"\d");
+    }
+
+    #[test]
+    fn escaped_quote() {
+        let statements = parse(r#"set system login message "say \"hi\"""#).unwrap();
+        assert_eq!(statements[0].arguments().last().unwrap(), r#"say "hi""#);
+        assert_eq!(statements[0].to_string(), r#"set system login message "say \"hi\"""#);
+    }
+
+    #[test]
+    fn trailing_backslash() {
+        let error = parse("set system host-name r1\nset system domain-name \\").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert_eq!(error.message, "trailing backslash");
+    }
+
+    #[test]
+    fn unterminated_quote() {
+        let error = parse("set system host-name r1\nset system login message \"hello\nset system ntp").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert_eq!(error.message, "unterminated quote");
+    }
+
+    #[test]
+    fn lines_and_comments() {
+        let statements = parse("# comment\nset system host-name r1\n\nset a b; delete c\n").unwrap();
+        let lines: Vec<usize> = statements.iter().map(Statement::line).collect();
+        assert_eq!(lines, [2, 4, 4]);
+        assert_eq!(statements[2].verb(), "delete");
+    }
+
+    #[test]
+    fn invalid_statements() {
+        let (statements, errors) = parse_all("show system\nset\nrename a b\nset a b");
+        assert_eq!(statements.len(), 1);
+        let lines: Vec<usize> = errors.iter().map(|error| error.line).collect();
+        assert_eq!(lines, [1, 2, 3]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let input = concat!(
+            "set interfaces ge-0/0/0 description \"a; b\"\n",
+            "set policy-options community c members \"^65000:.* (1|2)$\"\n",
+            "set system login message \"say \\\"hi\\\" \\\\\"\n",
+            "annotate system \"# not a comment\"\n",
+            "delete protocols ospf",
+        );
+        let statements = parse(input).unwrap();
+        let rendered = to_configuration_set(&statements);
+        assert_eq!(rendered, input);
+        assert_eq!(parse(&rendered).unwrap(), statements);
+    }
+}
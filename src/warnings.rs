@@ -0,0 +1,33 @@
+use rucli::netconf::error::NETCONFError;
+use rucli::netconf::Outcome;
+
+/// Decides what happens with warnings the router replies with.
+#[derive(Default)]
+pub struct WarningPolicy {
+    /// Fail on the first warning which is not ignored
+    pub as_errors: bool,
+    /// Warnings whose message contains one of these are dropped
+    pub ignore: Vec<String>,
+}
+
+impl WarningPolicy {
+    /// Prints the remaining warnings of `outcome` and returns its value, or fails
+    /// if warnings are treated as errors.
+    pub fn check<T>(&self, outcome: Outcome<T>) -> Result<T, NETCONFError> {
+        let mut warnings = outcome.warnings.into_iter().filter(|warning| {
+            !self
+                .ignore
+                .iter()
+                .any(|pattern| warning.error_message.contains(pattern.as_str()))
+        });
+        if self.as_errors {
+            if let Some(warning) = warnings.next() {
+                return Err(warning.into());
+            }
+        }
+        for warning in warnings {
+            eprintln!("{}", warning);
+        }
+        Ok(outcome.value)
+    }
+}